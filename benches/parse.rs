@@ -0,0 +1,54 @@
+//! Compares `Iter` and `StrIter` on a large generated file.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use sd_dotenv::{Iter, StrIter};
+use std::fmt::Write;
+
+const ENTRIES: usize = 20_000;
+
+/// Mostly plain values, as in generated files, with some quoting and substitution mixed in.
+fn source() -> String {
+    let mut source = String::new();
+    for index in 0..ENTRIES {
+        let _ = match index % 10 {
+            0 => writeln!(source, "# section {index}"),
+            1 => writeln!(source, "QUOTED_{index}='single quoted value {index}'"),
+            2 => writeln!(source, "DOUBLE_{index}=\"double quoted value {index}\""),
+            3 => writeln!(
+                source,
+                "SUBSTITUTED_{index}=\"${{PLAIN_{}}}/path\"",
+                index.saturating_sub(5)
+            ),
+            4 => writeln!(source, "ESCAPED_{index}=\"line\\nbreak\""),
+            _ => writeln!(source, "PLAIN_{index}=value-{index}"),
+        };
+    }
+    source
+}
+
+fn parse(c: &mut Criterion) {
+    let source = source();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+
+    group.bench_function("Iter", |b| {
+        b.iter(|| {
+            Iter::new(black_box(source.as_bytes()))
+                .collect::<sd_dotenv::Result<Vec<_>>>()
+                .unwrap()
+        });
+    });
+    group.bench_function("StrIter", |b| {
+        b.iter(|| {
+            StrIter::new(black_box(&source))
+                .collect::<sd_dotenv::Result<Vec<_>>>()
+                .unwrap()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,596 @@
+//! A language server for .env files, speaking the Language Server Protocol over stdin and
+//! stdout.
+//!
+//! It reports lines that fail to parse, shows the value of `$NAME` and `${NAME}` references
+//! on hover, goes to the line that defines a reference, completes the keys of the file and
+//! formats it.
+
+#![warn(
+    clippy::all,
+    clippy::pedantic,
+    clippy::correctness,
+    clippy::perf,
+    clippy::style,
+    clippy::suspicious,
+    clippy::complexity,
+    clippy::nursery,
+    clippy::unwrap_used,
+    unused_qualifications,
+    rust_2018_idioms,
+    clippy::expect_used,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_allocation,
+    clippy::as_conversions,
+    clippy::dbg_macro,
+    clippy::deprecated_cfg_attr,
+    clippy::separated_literal_suffix,
+    deprecated
+)]
+#![forbid(unsafe_code, deprecated_in_future)]
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationType, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, Formatting, GotoDefinition, HoverRequest, Request as RequestType,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, Position, PublishDiagnosticsParams, Range,
+    ServerCapabilities, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Uri,
+};
+use sd_dotenv::{Dialect, Entry, EntryIter, Reference};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+fn main() -> Result<(), BoxError> {
+    let (connection, io_threads) = Connection::stdio();
+    run(&connection)?;
+    // the writer thread only finishes once the connection's sender is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Serves a client on `connection` until it shuts the server down.
+fn run(connection: &Connection) -> Result<(), BoxError> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![String::from("$"), String::from("{")]),
+            ..CompletionOptions::default()
+        }),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                connection.sender.send(server.request(request).into())?;
+            }
+            Message::Notification(notification) => {
+                if let Some(params) = server.notification(notification) {
+                    let notification =
+                        Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+                    connection.sender.send(notification.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The documents that the client has opened.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Document>,
+}
+
+impl Server {
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            HoverRequest::METHOD => respond::<HoverRequest>(request, |params| self.hover(&params)),
+            GotoDefinition::METHOD => {
+                respond::<GotoDefinition>(request, |params| self.definition(params))
+            }
+            Completion::METHOD => respond::<Completion>(request, |params| self.completion(&params)),
+            Formatting::METHOD => respond::<Formatting>(request, |params| self.formatting(&params)),
+            _ => error_response(
+                request.id,
+                ErrorCode::MethodNotFound,
+                "unsupported request".to_owned(),
+            ),
+        }
+    }
+
+    /// Keeps track of opened documents, returning the diagnostics of a document that changed.
+    fn notification(&mut self, notification: Notification) -> Option<PublishDiagnosticsParams> {
+        let (uri, document) = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidOpenTextDocument as NotificationType>::Params>(
+                        DidOpenTextDocument::METHOD,
+                    )
+                    .ok()?;
+                let document = params.text_document;
+                (document.uri, Document::new(document.text))
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidChangeTextDocument as NotificationType>::Params>(
+                        DidChangeTextDocument::METHOD,
+                    )
+                    .ok()?;
+                // the server asks for the full text, so the last change has all of it
+                let text = params.content_changes.into_iter().last()?.text;
+                (params.text_document.uri, Document::new(text))
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification
+                    .extract::<<DidCloseTextDocument as NotificationType>::Params>(
+                        DidCloseTextDocument::METHOD,
+                    )
+                    .ok()?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                return Some(PublishDiagnosticsParams::new(uri, Vec::new(), None));
+            }
+            _ => return None,
+        };
+
+        let diagnostics = document.diagnostics.clone();
+        self.documents.insert(uri.clone(), document);
+        Some(PublishDiagnosticsParams::new(uri, diagnostics, None))
+    }
+
+    /// The document and byte offset that `params` point to.
+    fn locate(&self, params: &TextDocumentPositionParams) -> Option<(&Document, usize)> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        let offset = offset(&document.text, params.position);
+        Some((document, offset))
+    }
+
+    /// Shows the value of the variable that a reference refers to.
+    fn hover(&self, params: &HoverParams) -> Option<Hover> {
+        let (document, offset) = self.locate(&params.text_document_position_params)?;
+        let (referencing, reference) = document.reference_at(offset)?;
+
+        let name = &reference.name;
+        let defined = document
+            .definition(name, referencing)
+            .map(|entry| entry.value.as_str());
+        let value = match document.dialect.resolve(name, defined) {
+            None => format!("`{name}` is not set"),
+            Some(value) if Some(value.as_str()) == defined => format!("```\n{name}={value}\n```"),
+            Some(value) => format!("```\n{name}={value}\n```\nfrom the environment"),
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(range(&document.text, reference.span.bytes.clone())),
+        })
+    }
+
+    /// Goes from a reference to the key that defines the variable.
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let params = params.text_document_position_params;
+        let (document, offset) = self.locate(&params)?;
+        let (referencing, reference) = document.reference_at(offset)?;
+        let entry = document.definition(&reference.name, referencing)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            params.text_document.uri,
+            range(&document.text, entry.key_span.bytes.clone()),
+        )))
+    }
+
+    /// Completes the keys defined in the document.
+    fn completion(&self, params: &CompletionParams) -> Option<CompletionResponse> {
+        let (document, _) = self.locate(&params.text_document_position)?;
+
+        let mut items: Vec<CompletionItem> = Vec::new();
+        for entry in &document.entries {
+            if items.iter().all(|item| item.label != entry.key) {
+                items.push(CompletionItem {
+                    label: entry.key.clone(),
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    detail: Some(entry.value.clone()),
+                    ..CompletionItem::default()
+                });
+            }
+        }
+        Some(CompletionResponse::Array(items))
+    }
+
+    /// Formats the whole document, unless some of it fails to parse.
+    fn formatting(&self, params: &DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        let formatted = sd_dotenv::fmt(&document.text).ok()?;
+        if formatted == document.text {
+            return Some(Vec::new());
+        }
+        let all = range(&document.text, 0..document.text.len());
+        Some(vec![TextEdit::new(all, formatted)])
+    }
+}
+
+/// An opened document, parsed whenever it changes.
+struct Document {
+    text: String,
+    dialect: Dialect,
+    entries: Vec<Entry>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let mut entries = Vec::new();
+        let mut diagnostics = Vec::new();
+        let dialect = Dialect::dotenv();
+        let mut iter = EntryIter::new(&text).dialect(dialect);
+        while let Some(item) = iter.next() {
+            let offset = iter.line_offset();
+            let (span, message) = match item {
+                Ok(entry) => {
+                    entries.push(entry);
+                    continue;
+                }
+                Err(sd_dotenv::Error::LineParse(err)) => {
+                    let span = err.span();
+                    (
+                        offset + span.start..offset + span.end,
+                        err.kind().to_string(),
+                    )
+                }
+                Err(err) => (offset..offset, err.to_string()),
+            };
+            diagnostics.push(Diagnostic {
+                range: range(&text, span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some(String::from("dotenv")),
+                message,
+                ..Diagnostic::default()
+            });
+        }
+
+        Self {
+            text,
+            dialect,
+            entries,
+            diagnostics,
+        }
+    }
+
+    /// The reference at `offset`, along with the entry whose value contains it.
+    fn reference_at(&self, offset: usize) -> Option<(&Entry, &Reference)> {
+        self.entries.iter().find_map(|entry| {
+            let reference = entry
+                .references
+                .iter()
+                .find(|reference| reference.span.bytes.contains(&offset))?;
+            Some((entry, reference))
+        })
+    }
+
+    /// The entry that substitutions of `name` in `referencing` use, which is its first
+    /// definition before `referencing`, as the parser only sees the keys defined so far.
+    fn definition(&self, name: &str, referencing: &Entry) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .take_while(|entry| entry.key_span.bytes.start < referencing.key_span.bytes.start)
+            .find(|entry| entry.key == name)
+    }
+}
+
+/// Handles `request` as an `R`, or responds with an error if its parameters are invalid.
+fn respond<R: RequestType>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    let id = request.id.clone();
+    match request.extract::<R::Params>(R::METHOD) {
+        Ok((id, params)) => Response::new_ok(id, handler(params)),
+        Err(err) => error_response(id, ErrorCode::InvalidParams, err.to_string()),
+    }
+}
+
+#[allow(clippy::as_conversions)]
+fn error_response(id: RequestId, code: ErrorCode, message: String) -> Response {
+    Response::new_err(id, code as i32, message)
+}
+
+/// The LSP range of the bytes `span` within `text`.
+fn range(text: &str, span: std::ops::Range<usize>) -> Range {
+    Range::new(position(text, span.start), position(text, span.end))
+}
+
+/// The LSP position of byte `offset` within `text`, in UTF-16 code units.
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    Position::new(
+        u32::try_from(line).unwrap_or(u32::MAX),
+        u32::try_from(character).unwrap_or(u32::MAX),
+    )
+}
+
+/// The byte offset of `position` within `text`, clamped to the end of its line.
+fn offset(text: &str, position: Position) -> usize {
+    let line = usize::try_from(position.line).unwrap_or(usize::MAX);
+    let line_start = match line.checked_sub(1) {
+        None => 0,
+        Some(index) => match text.match_indices('\n').nth(index) {
+            Some((newline, _)) => newline + 1,
+            None => return text.len(),
+        },
+    };
+
+    let mut units = 0;
+    for (index, c) in text[line_start..].char_indices() {
+        if units >= position.character || c == '\n' {
+            return line_start + index;
+        }
+        units += u32::try_from(c.len_utf16()).unwrap_or(u32::MAX);
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread::{self, JoinHandle};
+
+    use lsp_types::notification::{Exit, Initialized};
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{
+        DidChangeTextDocumentParams, DidOpenTextDocumentParams, FormattingOptions,
+        InitializeParams, InitializedParams, PartialResultParams, TextDocumentContentChangeEvent,
+        TextDocumentIdentifier, TextDocumentItem, VersionedTextDocumentIdentifier,
+        WorkDoneProgressParams,
+    };
+
+    use super::*;
+
+    /// A client talking to a server that runs on another thread.
+    struct Client {
+        connection: Connection,
+        server: Option<JoinHandle<()>>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (client, server) = Connection::memory();
+            let server = thread::spawn(move || run(&server).unwrap());
+            let mut client = Self {
+                connection: client,
+                server: Some(server),
+                next_id: 0,
+            };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(InitializedParams {});
+            client
+        }
+
+        fn request<R: RequestType>(&mut self, params: R::Params) -> serde_json::Value {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            let request = Request::new(id.clone(), R::METHOD.to_owned(), params);
+            self.connection.sender.send(request.into()).unwrap();
+
+            loop {
+                match self.connection.receiver.recv().unwrap() {
+                    Message::Response(response) if response.id == id => {
+                        assert!(response.error.is_none(), "{:?}", response.error);
+                        return response.result.unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn notify<N: NotificationType>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.to_owned(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            loop {
+                if let Message::Notification(notification) =
+                    self.connection.receiver.recv().unwrap()
+                {
+                    if let Ok(params) =
+                        notification.extract::<PublishDiagnosticsParams>(PublishDiagnostics::METHOD)
+                    {
+                        return params;
+                    }
+                }
+            }
+        }
+
+        fn open(&self, text: &str) -> PublishDiagnosticsParams {
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    uri(),
+                    String::from("dotenv"),
+                    1,
+                    text.to_owned(),
+                ),
+            });
+            self.diagnostics()
+        }
+    }
+
+    impl Drop for Client {
+        fn drop(&mut self) {
+            if let Some(server) = self.server.take() {
+                if !thread::panicking() {
+                    self.request::<Shutdown>(());
+                    self.notify::<Exit>(());
+                    server.join().unwrap();
+                }
+            }
+        }
+    }
+
+    fn at(line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(uri()),
+            Position::new(line, character),
+        )
+    }
+
+    fn uri() -> Uri {
+        "file:///project/.env".parse().unwrap()
+    }
+
+    const SOURCE: &str = "LSP_HOST=localhost\nLSP_URL=\"http://${LSP_HOST}/\"\n";
+
+    #[test]
+    fn test_diagnostics() {
+        let client = Client::start();
+        let diagnostics = client.open("LSP_OK=1\nLSP_BAD=a b\n").diagnostics;
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unexpected character");
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 10), Position::new(1, 11))
+        );
+
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: String::from("LSP_BAD='a b'\n"),
+            }],
+        });
+        assert!(client.diagnostics().diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_hover_and_definition() {
+        let mut client = Client::start();
+        client.open(SOURCE);
+
+        let hover: Hover = serde_json::from_value(client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(1, 20),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        }))
+        .unwrap();
+        let contents = match hover.contents {
+            HoverContents::Markup(contents) => contents,
+            other => panic!("Expected markup, got {other:?}"),
+        };
+        assert_eq!(contents.value, "```\nLSP_HOST=localhost\n```");
+        assert_eq!(
+            hover.range,
+            Some(Range::new(Position::new(1, 16), Position::new(1, 27)))
+        );
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: at(1, 20),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        let definition: GotoDefinitionResponse = serde_json::from_value(definition).unwrap();
+        assert_eq!(
+            definition,
+            GotoDefinitionResponse::Scalar(Location::new(
+                uri(),
+                Range::new(Position::new(0, 0), Position::new(0, 8))
+            ))
+        );
+
+        let outside = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(0, 2),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        assert_eq!(outside, serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_forward_reference_has_no_definition() {
+        let mut client = Client::start();
+        client.open("LSP_EARLY=${LSP_LATER}\nLSP_LATER=1\n");
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: at(0, 12),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        });
+        assert_eq!(definition, serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut client = Client::start();
+        client.open(SOURCE);
+
+        let completion = client.request::<Completion>(CompletionParams {
+            text_document_position: at(1, 17),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+            context: None,
+        });
+        let items = match serde_json::from_value(completion).unwrap() {
+            CompletionResponse::Array(items) => items,
+            CompletionResponse::List(list) => {
+                panic!("Expected an array of completions, got {list:?}")
+            }
+        };
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["LSP_HOST", "LSP_URL"]);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut client = Client::start();
+        client.open("  # comment  \n\n\nexport  LSP_A = 'a b'   # trailing\nLSP_B =\n");
+
+        let edits = client.request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            options: FormattingOptions::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        let edits: Vec<TextEdit> = serde_json::from_value(edits).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].new_text,
+            "# comment\n\nexport LSP_A='a b' # trailing\nLSP_B=\n"
+        );
+    }
+
+    #[test]
+    fn test_positions_use_utf16() {
+        let text = "A=ä😀\nB=x";
+
+        assert_eq!(position(text, 8), Position::new(0, 5));
+        assert_eq!(offset(text, Position::new(0, 5)), 8);
+        assert_eq!(offset(text, Position::new(0, 99)), 8);
+        assert_eq!(offset(text, Position::new(1, 2)), 11);
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+    }
+}
@@ -0,0 +1,187 @@
+//! The `dotenv` command line tool.
+
+#![warn(
+    clippy::all,
+    clippy::pedantic,
+    clippy::correctness,
+    clippy::perf,
+    clippy::style,
+    clippy::suspicious,
+    clippy::complexity,
+    clippy::nursery,
+    clippy::unwrap_used,
+    unused_qualifications,
+    rust_2018_idioms,
+    clippy::expect_used,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_allocation,
+    clippy::as_conversions,
+    clippy::dbg_macro,
+    clippy::deprecated_cfg_attr,
+    clippy::separated_literal_suffix,
+    deprecated
+)]
+#![forbid(unsafe_code, deprecated_in_future)]
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sd_dotenv::Formatter;
+
+const USAGE: &str = "\
+usage: dotenv fmt [options] [file]...
+
+Formats .env files, or standard input if no file is given, and writes the result to
+standard output.
+
+options:
+    -w, --write        rewrite the files in place
+    --check            list the files that are not formatted, and fail if there are any
+    --remove-export    remove `export` prefixes
+    --no-sort          keep the order of keys";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = run(&args, &mut io::stdin().lock(), &mut io::stdout().lock());
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("dotenv: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs the command in `args`, returning whether it succeeded or a message if it could not
+/// run.
+fn run(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<bool, String> {
+    match args.split_first() {
+        Some((command, args)) if command == "fmt" => fmt(args, stdin, stdout),
+        Some((help, _)) if help == "-h" || help == "--help" => {
+            writeln!(stdout, "{USAGE}").map_err(|err| err.to_string())?;
+            Ok(true)
+        }
+        Some((command, _)) => Err(format!("unknown command '{command}'\n\n{USAGE}")),
+        None => Err(USAGE.to_owned()),
+    }
+}
+
+fn fmt(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<bool, String> {
+    let mut formatter = Formatter::new();
+    let mut write = false;
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-w" | "--write" => write = true,
+            "--check" => check = true,
+            "--remove-export" => formatter = formatter.remove_export(true),
+            "--no-sort" => formatter = formatter.sort_keys(false),
+            "-h" | "--help" => return run(&[String::from("--help")], stdin, stdout),
+            option if option.starts_with('-') => {
+                return Err(format!("unknown option '{option}'\n\n{USAGE}"))
+            }
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+
+    let output_error = |err: io::Error| format!("cannot write output: {err}");
+    if files.is_empty() {
+        if write {
+            return Err(String::from("--write needs at least one file"));
+        }
+        let mut source = String::new();
+        stdin
+            .read_to_string(&mut source)
+            .map_err(|err| format!("cannot read standard input: {err}"))?;
+        let output = formatter
+            .format(&source)
+            .map_err(|err| format!("<stdin>: {err}"))?;
+        if check {
+            return Ok(output == source);
+        }
+        stdout.write_all(output.as_bytes()).map_err(output_error)?;
+        return Ok(true);
+    }
+
+    let mut formatted_all = true;
+    for file in files {
+        let source =
+            fs::read_to_string(&file).map_err(|err| format!("{}: {err}", file.display()))?;
+        let output = formatter
+            .format(&source)
+            .map_err(|err| format!("{}: {err}", file.display()))?;
+        if check {
+            if output != source {
+                writeln!(stdout, "{}", file.display()).map_err(output_error)?;
+                formatted_all = false;
+            }
+        } else if write {
+            if output != source {
+                fs::write(&file, output).map_err(|err| format!("{}: {err}", file.display()))?;
+            }
+        } else {
+            stdout.write_all(output.as_bytes()).map_err(output_error)?;
+        }
+    }
+    Ok(formatted_all)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_with(args: &[&str], stdin: &str) -> (Result<bool, String>, String) {
+        let args: Vec<String> = args.iter().map(|&arg| arg.to_owned()).collect();
+        let mut stdout = Vec::new();
+        let result = run(&args, &mut stdin.as_bytes(), &mut stdout);
+        (result, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn test_fmt_stdin() {
+        let (result, stdout) = run_with(&["fmt"], "B = 2\nexport A='1'\n");
+        assert_eq!(result, Ok(true));
+        assert_eq!(stdout, "export A=1\nB=2\n");
+
+        let (_, stdout) = run_with(&["fmt", "--remove-export", "--no-sort"], "B=2\nexport A=1");
+        assert_eq!(stdout, "B=2\nA=1\n");
+
+        assert_eq!(run_with(&["fmt", "--check"], "A=1\n").0, Ok(true));
+        assert_eq!(run_with(&["fmt", "--check"], "A = 1\n").0, Ok(false));
+    }
+
+    #[test]
+    fn test_fmt_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "B=2\nA=1").unwrap();
+        let file = path.to_str().unwrap();
+
+        let (result, stdout) = run_with(&["fmt", "--check", file], "");
+        assert_eq!(result, Ok(false));
+        assert_eq!(stdout.trim_end(), file);
+
+        assert_eq!(run_with(&["fmt", "-w", file], "").0, Ok(true));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "A=1\nB=2\n");
+        assert_eq!(run_with(&["fmt", "--check", file], "").0, Ok(true));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(run_with(&[], "").0.is_err());
+        assert!(run_with(&["lint"], "").0.is_err());
+        assert!(run_with(&["fmt", "--unknown"], "").0.is_err());
+        assert!(run_with(&["fmt", "-w"], "").0.is_err());
+
+        let (result, _) = run_with(&["fmt"], "A=1\nB=a b");
+        assert!(result
+            .unwrap_err()
+            .starts_with("<stdin>: Error parsing line"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::str::Lines;
+
+use crate::errors::{Error, Result};
+use crate::iter::{load_methods, DuplicatePolicy};
+use crate::parse::{self, Dialect, Lookup};
+
+/// An iterator over the variables of a .env file that is already in memory.
+///
+/// It yields the same pairs as `Iter`, but keys are borrowed from the source, and so are values
+/// that need no unescaping or substitution. Only the values that do are allocated, which makes
+/// a difference for large generated files. `# @include` directives are not followed.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv::StrIter;
+/// use std::borrow::Cow;
+///
+/// let source = "APP_HOST=localhost\nURL=\"http://${APP_HOST}\"";
+/// let pairs = StrIter::new(source)
+///     .collect::<sd_dotenv::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert!(matches!(pairs[0], ("APP_HOST", Cow::Borrowed("localhost"))));
+/// assert!(matches!(&pairs[1], ("URL", Cow::Owned(url)) if url == "http://localhost"));
+/// ```
+pub struct StrIter<'a> {
+    lines: Lines<'a>,
+    line: usize,
+    duplicates: DuplicatePolicy,
+    defined: Defined<'a>,
+    // names that substitutions may refer to, the only ones whose owned values are kept
+    referenced: HashSet<&'a str>,
+}
+
+/// The line each key was first defined on, and the value substitutions see, which is left
+/// empty for owned values that no substitution refers to.
+struct Defined<'a>(HashMap<&'a str, (usize, Cow<'a, str>)>);
+
+impl Lookup for Defined<'_> {
+    fn lookup(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|(_, value)| value.as_ref())
+    }
+}
+
+impl<'a> StrIter<'a> {
+    #[must_use]
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            lines: source.lines(),
+            line: 0,
+            duplicates: DuplicatePolicy::default(),
+            defined: Defined(HashMap::new()),
+            referenced: referenced_names(source),
+        }
+    }
+
+    /// Sets what happens to keys that are defined more than once.
+    #[must_use]
+    pub const fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    load_methods!(self.owned());
+
+    fn owned(self) -> impl Iterator<Item = Result<(String, String)>> + 'a {
+        self.map(|item| item.map(|(key, value)| (key.to_owned(), value.into_owned())))
+    }
+}
+
+impl<'a> Iterator for StrIter<'a> {
+    type Item = Result<(&'a str, Cow<'a, str>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match simple_entry(trimmed) {
+                Some((key, value)) => (key, Cow::Borrowed(value)),
+                None => match parse::parse_line(line, &self.defined, Dialect::dotenv()) {
+                    Ok(Some(entry)) => (&line[entry.key_span], Cow::Owned(entry.value)),
+                    Ok(None) => continue,
+                    Err(err) => return Some(Err(err)),
+                },
+            };
+
+            let first_line = match self.defined.0.get(key) {
+                Some(&(first_line, _)) => match self.duplicates {
+                    DuplicatePolicy::FirstWins => continue,
+                    DuplicatePolicy::LastWins => first_line,
+                    DuplicatePolicy::Error => {
+                        let err = Error::DuplicateKey(key.to_owned(), first_line, self.line);
+                        return Some(Err(err));
+                    }
+                },
+                None => self.line,
+            };
+
+            let stored = match &value {
+                Cow::Owned(_) if !self.referenced.contains(key) => Cow::Borrowed(""),
+                value => value.clone(),
+            };
+            self.defined.0.insert(key, (first_line, stored));
+            return Some(Ok((key, value)));
+        }
+    }
+}
+
+/// Returns every name that `$NAME` or `${NAME}` in `source` may substitute. Quotes and escapes
+/// are ignored, so this includes names that are never actually substituted.
+fn referenced_names(source: &str) -> HashSet<&str> {
+    source
+        .match_indices('$')
+        .filter_map(|(start, _)| {
+            let rest = &source[start + 1..];
+            let name = rest.strip_prefix('{').map_or_else(
+                || rest.split(|c: char| !c.is_alphanumeric()).next(),
+                |block| block.split(['}', '\n']).next(),
+            )?;
+            (!name.is_empty()).then_some(name)
+        })
+        .collect()
+}
+
+/// Splits a trimmed `KEY=value` line whose value can be borrowed as is, or returns `None` if
+/// the line needs the full parser.
+///
+/// That is the case for an unquoted value without escapes or substitutions, or a quoted one
+/// without anything to unescape or substitute inside the quotes, followed by nothing but a
+/// comment.
+fn simple_entry(line: &str) -> Option<(&str, &str)> {
+    let (mut key, mut rest) = split_key(line)?;
+    rest = rest.trim_start();
+    // export can be either an optional prefix or a key itself
+    if key == "export" && !rest.is_empty() && !rest.starts_with('=') {
+        (key, rest) = split_key(rest)?;
+        rest = rest.trim_start();
+    }
+
+    let rest = rest.strip_prefix('=')?.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        return Some((key, ""));
+    }
+
+    let (value, tail) = match rest.as_bytes()[0] {
+        b'\'' => {
+            let end = rest[1..].find('\'')? + 1;
+            (&rest[1..end], &rest[end + 1..])
+        }
+        b'"' => {
+            let end = rest[1..].find(['"', '\\', '$'])? + 1;
+            if rest.as_bytes()[end] != b'"' {
+                return None;
+            }
+            (&rest[1..end], &rest[end + 1..])
+        }
+        _ => {
+            let end = rest
+                .find([' ', '\t', '\\', '\'', '"', '$'])
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        }
+    };
+
+    // whitespace ends the value, after which only a comment may follow
+    if !tail.is_empty() {
+        let comment = tail.trim_start_matches([' ', '\t']);
+        if comment.len() == tail.len() || !(comment.is_empty() || comment.starts_with('#')) {
+            return None;
+        }
+    }
+    Some((key, value))
+}
+
+/// Splits off a key at the start of `line`, if it starts with a valid one.
+fn split_key(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(line.len());
+    Some(line.split_at(end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{iter::Iter, lock_env};
+
+    // every value on its own line, so that each is parsed by itself
+    const SOURCE: &str = r#"
+# comment
+PLAIN=value
+  SPACED  =  spaced   # comment
+export EXPORTED=1
+export=key
+HASH=a#b
+EMPTY=
+EMPTY_COMMENT= # comment
+SINGLE='single $PLAIN "x"'
+DOUBLE="double 'x'"
+CONCATENATED='a'"b"
+SUBSTITUTED=$PLAIN
+SUBSTITUTED_QUOTED="${SINGLE}!"
+ESCAPED=a\ b
+FROM_ESCAPED=${ESCAPED}
+UNICODE=ünï"cödé"
+PLAIN=duplicate
+"#;
+
+    #[test]
+    fn test_matches_iter() {
+        let _lock = lock_env();
+        for policy in [DuplicatePolicy::FirstWins, DuplicatePolicy::LastWins] {
+            let expected: Vec<(String, String)> = Iter::new(SOURCE.as_bytes())
+                .duplicates(policy)
+                .collect::<Result<_>>()
+                .unwrap();
+            let actual: Vec<(String, String)> = StrIter::new(SOURCE)
+                .duplicates(policy)
+                .owned()
+                .collect::<Result<_>>()
+                .unwrap();
+
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_borrows_simple_values() {
+        let _lock = lock_env();
+        let pairs: Vec<_> = StrIter::new(SOURCE).collect::<Result<_>>().unwrap();
+
+        for (key, value) in pairs {
+            let owned = [
+                "CONCATENATED",
+                "SUBSTITUTED",
+                "SUBSTITUTED_QUOTED",
+                "ESCAPED",
+                "FROM_ESCAPED",
+                "UNICODE",
+            ];
+            assert_eq!(
+                matches!(value, Cow::Owned(_)),
+                owned.contains(&key),
+                "{key}={value}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_referenced_names() {
+        let mut names: Vec<_> = referenced_names("A=$B_C\nD=\"${E.F}$$G\"\nH='$'")
+            .into_iter()
+            .collect();
+        names.sort_unstable();
+
+        assert_eq!(names, ["B", "E.F", "G"]);
+    }
+
+    #[test]
+    fn test_errors_match_iter() {
+        let _lock = lock_env();
+        for source in [
+            "KEY=a b",
+            "KEY='open",
+            "1KEY=x",
+            "KEY",
+            "KEY=\\q",
+            "D=1\nD=2",
+        ] {
+            let expected = Iter::new(source.as_bytes())
+                .duplicates(DuplicatePolicy::Error)
+                .collect::<Result<Vec<_>>>()
+                .unwrap_err();
+            let actual = StrIter::new(source)
+                .duplicates(DuplicatePolicy::Error)
+                .collect::<Result<Vec<_>>>()
+                .unwrap_err();
+
+            assert_eq!(actual.to_string(), expected.to_string(), "{source}");
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+/// The keys that were added, changed or removed between two versions of a .env file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    /// Keys that were not present before, with their new values.
+    pub added: BTreeMap<String, String>,
+    /// Keys whose value changed, with their `(old, new)` values.
+    pub changed: BTreeMap<String, (String, String)>,
+    /// Keys that are no longer present, with their last values.
+    pub removed: BTreeMap<String, String>,
+}
+
+impl Diff {
+    /// Computes the changes needed to go from `old` to `new`.
+    #[must_use]
+    pub fn between(old: &HashMap<String, String>, new: &HashMap<String, String>) -> Self {
+        let mut diff = Self::default();
+
+        for (key, value) in new {
+            match old.get(key) {
+                None => {
+                    diff.added.insert(key.clone(), value.clone());
+                }
+                Some(previous) if previous != value => {
+                    diff.changed
+                        .insert(key.clone(), (previous.clone(), value.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (key, value) in old {
+            if !new.contains_key(key) {
+                diff.removed.insert(key.clone(), value.clone());
+            }
+        }
+
+        diff
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Applies the changes to the environment of the current process.
+    ///
+    /// Unlike `Iter::load`, this overwrites variables that are already set, since they are
+    /// assumed to come from an earlier version of the same file.
+    pub fn apply(&self) {
+        for (key, value) in &self.added {
+            env::set_var(key, value);
+        }
+        for (key, (_, value)) in &self.changed {
+            env::set_var(key, value);
+        }
+        for key in self.removed.keys() {
+            env::remove_var(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_between() {
+        let old = map(&[("KEPT", "1"), ("CHANGED", "old"), ("REMOVED", "x")]);
+        let new = map(&[("KEPT", "1"), ("CHANGED", "new"), ("ADDED", "y")]);
+
+        let diff = Diff::between(&old, &new);
+
+        assert_eq!(diff.added, [("ADDED".to_string(), "y".to_string())].into());
+        assert_eq!(
+            diff.changed,
+            [(
+                "CHANGED".to_string(),
+                ("old".to_string(), "new".to_string())
+            )]
+            .into()
+        );
+        assert_eq!(
+            diff.removed,
+            [("REMOVED".to_string(), "x".to_string())].into()
+        );
+        assert!(Diff::between(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::errors::{Error, ParseErrorKind, Result};
+use crate::parse::{self, Dialect, ParsedEntry};
+
+/// A `KEY=value` definition, with where its parts are in the source.
+///
+/// Produced by `EntryIter` for tools such as editors and linters, which need more than the
+/// `(key, value)` pairs that `Iter` yields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    /// The value with quotes removed, escapes decoded and substitutions applied.
+    pub value: String,
+    /// The value as written, excluding surrounding whitespace and any trailing comment.
+    pub raw_value: String,
+    pub quoting: Quoting,
+    pub key_span: Span,
+    /// The span of `raw_value`, which is empty at the position of the missing value for
+    /// `KEY=`.
+    pub value_span: Span,
+    /// The `$NAME` and `${NAME}` references within the value.
+    pub references: Vec<Reference>,
+}
+
+/// A reference to another variable within a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+}
+
+/// How the raw value of an `Entry` is quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Quoting {
+    /// No quotes at all, including an empty value.
+    Unquoted,
+    /// Entirely within one pair of single quotes.
+    Single,
+    /// Entirely within one pair of double quotes.
+    Double,
+    /// Entirely within one pair of backticks, which some dialects allow.
+    Backtick,
+    /// Several quoted parts, or quoted and unquoted parts, such as `'a'"b"` or `a"b"`.
+    Mixed,
+}
+
+/// A range of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The byte range within the whole source.
+    pub bytes: Range<usize>,
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A position in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The character within the line, starting at 1.
+    pub column: usize,
+}
+
+/// An iterator over the entries of a .env file that is already in memory.
+///
+/// Every definition is yielded, including those of keys defined more than once, while
+/// substitutions see the first definition of a key, as they do with the default
+/// `DuplicatePolicy` of `Iter`. A line that fails to parse yields an error, after which
+/// iteration continues with the next line. `# @include` directives are not followed.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv::{EntryIter, Quoting};
+///
+/// let entry = EntryIter::new("# comment\nexport NAME='value'")
+///     .next()
+///     .unwrap()
+///     .unwrap();
+///
+/// assert_eq!(entry.key, "NAME");
+/// assert_eq!(entry.value, "value");
+/// assert_eq!(entry.raw_value, "'value'");
+/// assert_eq!(entry.quoting, Quoting::Single);
+/// assert_eq!(entry.key_span.bytes, 17..21);
+/// assert_eq!((entry.value_span.start.line, entry.value_span.start.column), (2, 13));
+/// ```
+pub struct EntryIter<'a> {
+    source: &'a str,
+    /// The byte offset of the next line.
+    offset: usize,
+    /// The number of the next line, starting at 1.
+    line: usize,
+    /// The byte offset of the line the last item was parsed from.
+    line_offset: usize,
+    dialect: Dialect,
+    substitution_data: HashMap<String, Option<String>>,
+}
+
+impl<'a> EntryIter<'a> {
+    #[must_use]
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            offset: 0,
+            line: 1,
+            line_offset: 0,
+            dialect: Dialect::dotenv(),
+            substitution_data: HashMap::new(),
+        }
+    }
+
+    /// Sets the dialect the source is parsed with. The default is `Dialect::dotenv`.
+    #[must_use]
+    pub const fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// The byte offset of the line that the last item was parsed from.
+    ///
+    /// This locates the span of a `ParseError`, which is relative to its line, within the
+    /// source.
+    #[must_use]
+    pub const fn line_offset(&self) -> usize {
+        self.line_offset
+    }
+
+    /// Takes the next line, returning its start offset and its text without the line break.
+    fn next_line(&mut self) -> Option<(usize, &'a str)> {
+        if self.offset >= self.source.len() {
+            return None;
+        }
+        let start = self.offset;
+        let rest = &self.source[start..];
+        let text = rest.split('\n').next().unwrap_or(rest);
+        self.offset += (text.len() + 1).min(rest.len());
+        self.line += 1;
+        Some((start, text.strip_suffix('\r').unwrap_or(text)))
+    }
+
+    /// Parses the lines from `start` on, joining the lines that follow while the dialect
+    /// allows a quoted value to continue on them. The joined lines are only parsed again once
+    /// one of them has the closing quote.
+    fn parse(&mut self, start: usize, mut text: &'a str) -> Result<Option<(&'a str, ParsedEntry)>> {
+        loop {
+            let err = match parse::parse_line(text, &self.substitution_data, self.dialect) {
+                Err(Error::LineParse(err))
+                    if err.kind() == ParseErrorKind::UnterminatedQuote
+                        && self.dialect.multiline() =>
+                {
+                    err
+                }
+                result => return result.map(|entry| entry.map(|entry| (text, entry))),
+            };
+            let quote = match text
+                .get(err.span().start..)
+                .and_then(|rest| rest.chars().next())
+            {
+                Some(quote) => quote,
+                None => return Err(Error::LineParse(err)),
+            };
+
+            loop {
+                match self.next_line() {
+                    Some((next, next_text)) => {
+                        text = &self.source[start..next + next_text.len()];
+                        if parse::closing_quote(next_text, quote).is_some() {
+                            break;
+                        }
+                    }
+                    None => return Err(Error::LineParse(err)),
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for EntryIter<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.line;
+            let (start, text) = self.next_line()?;
+            self.line_offset = start;
+            let (text, entry) = match self.parse(start, text) {
+                Ok(Some(parsed)) => parsed,
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            };
+
+            self.substitution_data
+                .entry(entry.key.clone())
+                .or_insert_with(|| Some(entry.value.clone()));
+
+            let span = |bytes: Range<usize>| Span {
+                start: position(text, line, bytes.start),
+                end: position(text, line, bytes.end),
+                bytes: start + bytes.start..start + bytes.end,
+            };
+            let raw_value = &text[entry.value_span.clone()];
+            return Some(Ok(Entry {
+                quoting: quoting(raw_value),
+                raw_value: raw_value.to_owned(),
+                key_span: span(entry.key_span),
+                value_span: span(entry.value_span),
+                references: entry
+                    .substitutions
+                    .into_iter()
+                    .map(|substitution| Reference {
+                        name: substitution.name,
+                        span: span(substitution.span),
+                    })
+                    .collect(),
+                key: entry.key,
+                value: entry.value,
+            }));
+        }
+    }
+}
+
+/// The position of byte `offset` within `text`, which starts at the beginning of `line`.
+fn position(text: &str, line: usize, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    Position {
+        line: line + before.matches('\n').count(),
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+fn quoting(raw: &str) -> Quoting {
+    let mut quotes = Vec::new();
+    let mut unquoted = false;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                quotes.push(c);
+                // escapes only apply within double quotes
+                while let Some(inner) = chars.next() {
+                    if inner == c {
+                        break;
+                    } else if inner == '\\' && c == '"' {
+                        chars.next();
+                    }
+                }
+            }
+            '\\' => {
+                unquoted = true;
+                chars.next();
+            }
+            _ => unquoted = true,
+        }
+    }
+
+    match (quotes.as_slice(), unquoted) {
+        ([], _) => Quoting::Unquoted,
+        (['\''], false) => Quoting::Single,
+        (['"'], false) => Quoting::Double,
+        (['`'], false) => Quoting::Backtick,
+        _ => Quoting::Mixed,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lock_env;
+
+    fn entries(source: &str) -> Vec<Entry> {
+        EntryIter::new(source).collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn test_spans() {
+        let _lock = lock_env();
+        let source =
+            "# ünïcödé\r\n\r\n  ENTRY_A = \"x y\"  # comment\r\nENTRY_B=\nENTRY_C=ä${ENTRY_A}";
+        let entries = entries(source);
+
+        assert_eq!(entries.len(), 3);
+        for entry in &entries {
+            assert_eq!(&source[entry.key_span.bytes.clone()], entry.key);
+            assert_eq!(&source[entry.value_span.bytes.clone()], entry.raw_value);
+        }
+
+        let a = &entries[0];
+        assert_eq!(a.value, "x y");
+        assert_eq!(a.quoting, Quoting::Double);
+        assert_eq!(a.key_span.start, Position { line: 3, column: 3 });
+        assert_eq!(
+            a.key_span.end,
+            Position {
+                line: 3,
+                column: 10
+            }
+        );
+        assert_eq!(
+            a.value_span.end,
+            Position {
+                line: 3,
+                column: 18
+            }
+        );
+
+        let b = &entries[1];
+        assert_eq!((b.raw_value.as_str(), b.quoting), ("", Quoting::Unquoted));
+        assert_eq!(b.value_span.start, Position { line: 4, column: 9 });
+        assert!(b.value_span.bytes.is_empty());
+
+        let c = &entries[2];
+        assert_eq!(
+            (c.value.as_str(), c.raw_value.as_str()),
+            ("äx y", "ä${ENTRY_A}")
+        );
+        assert_eq!(c.references[0].name, "ENTRY_A");
+        assert_eq!(&source[c.references[0].span.bytes.clone()], "${ENTRY_A}");
+        assert_eq!(
+            c.value_span.end,
+            Position {
+                line: 5,
+                column: 20
+            }
+        );
+    }
+
+    #[test]
+    fn test_quoting() {
+        for (raw, expected) in [
+            ("plain", Quoting::Unquoted),
+            (r"a\'b", Quoting::Unquoted),
+            ("'a\"b'", Quoting::Single),
+            (r#""a\"b""#, Quoting::Double),
+            ("`a`", Quoting::Backtick),
+            ("'a'\"b\"", Quoting::Mixed),
+            ("a'b'", Quoting::Mixed),
+        ] {
+            assert_eq!(quoting(raw), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn test_errors_and_duplicates() {
+        let _lock = lock_env();
+        let items: Vec<_> = EntryIter::new("DUP=1\nBAD=a b\nDUP=2\nUSE=$DUP").collect();
+
+        assert_eq!(items.len(), 4);
+        assert!(items[1].is_err());
+        let mut iter = EntryIter::new("# comment\nBAD=a b");
+        let err = match iter.next() {
+            Some(Err(Error::LineParse(err))) => err,
+            other => panic!("Expected a parse error, got {other:?}"),
+        };
+        assert_eq!(iter.line_offset() + err.span().start, 16);
+        let values: Vec<_> = items
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|entry| entry.value)
+            .collect();
+        assert_eq!(values, ["1", "2", "1"]);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let _lock = lock_env();
+        let source = "FIRST=1\nMULTI=\"one\ntwo\"\nLAST=2";
+        let entries: Vec<_> = EntryIter::new(source)
+            .dialect(Dialect::node())
+            .collect::<Result<_>>()
+            .unwrap();
+
+        let multi = &entries[1];
+        assert_eq!(multi.value, "one\ntwo");
+        assert_eq!(multi.value_span.start, Position { line: 2, column: 7 });
+        assert_eq!(multi.value_span.end, Position { line: 3, column: 5 });
+        assert_eq!(entries[2].key_span.start, Position { line: 4, column: 1 });
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::str::FromStr;
+use std::vec;
+
+use crate::diff::Diff;
+use crate::errors::{Error, Result};
+use crate::fmt::quote;
+use crate::iter::{glob_match, load_methods, Iter};
+use crate::parse;
+
+/// The variables of a .env file in the order they are defined, and the path it was read from.
+///
+/// Where `Iter` streams pairs, an `EnvFile` is a value that can be passed around, combined with
+/// other files, compared, loaded into the environment and written back. Each key appears once,
+/// at the position of its first definition.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv::{EnvFile, Precedence};
+///
+/// let mut env = EnvFile::from_path(".env").unwrap();
+/// env.merge(EnvFile::from_path(".env.local").unwrap(), Precedence::Incoming);
+///
+/// let before = EnvFile::from_path(".env.production").unwrap();
+/// for key in before.diff(&env).added.keys() {
+///     println!("{key} is only set locally");
+/// }
+///
+/// env.filter_prefix("APP_").save(".env.app").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvFile {
+    path: Option<PathBuf>,
+    vars: Vec<(String, String)>,
+    // position of every key in `vars`
+    index: HashMap<String, usize>,
+}
+
+/// Which value `EnvFile::merge` keeps for a key that both files define.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precedence {
+    /// The value of the file being merged into.
+    Existing,
+    /// The value of the file being merged in, like a later layer overriding an earlier one.
+    Incoming,
+}
+
+impl EnvFile {
+    /// Creates an empty file without a path.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the file at `path`, following its `# @include` directives.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut env = Self::read(crate::from_path_iter(&path)?)?;
+        env.path = Some(path.as_ref().to_path_buf());
+        Ok(env)
+    }
+
+    /// Collects the variables of `iter`, which may be configured with a dialect, filters or a
+    /// duplicate policy. Keys removed by `unset` directives are left out.
+    pub fn read<R: Read>(iter: Iter<R>) -> Result<Self> {
+        let mut env = Self::new();
+        for item in iter.changes() {
+            match item? {
+                (key, Some(value)) => {
+                    env.insert(key, value);
+                }
+                (key, None) => {
+                    env.remove(&key);
+                }
+            }
+        }
+        Ok(env)
+    }
+
+    /// The path the file was read from, if any.
+    #[must_use]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.position(key).map(|index| self.vars[index].1.as_str())
+    }
+
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Sets `key` to `value`, returning its previous value. A new key is added at the end,
+    /// while an existing key keeps its position.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> Option<String> {
+        let key = key.into();
+        let value = value.into();
+        if let Some(index) = self.position(&key) {
+            return Some(mem::replace(&mut self.vars[index].1, value));
+        }
+        self.push(key, value);
+        None
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.index.remove(key)?;
+        let (_, value) = self.vars.remove(index);
+        for (position, (key, _)) in self.vars.iter().enumerate().skip(index) {
+            self.index.insert(key.clone(), position);
+        }
+        Some(value)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// Returns an iterator over the variables, in order.
+    #[must_use]
+    pub fn iter(&self) -> EnvFileIter<'_> {
+        EnvFileIter {
+            inner: self.vars.iter(),
+        }
+    }
+
+    /// Adds the variables of `other` after those of this file. Keys defined in both keep their
+    /// position here, and the value chosen by `precedence`.
+    pub fn merge(&mut self, other: Self, precedence: Precedence) {
+        for (key, value) in other {
+            match self.position(&key) {
+                Some(index) if precedence == Precedence::Incoming => self.vars[index].1 = value,
+                Some(_) => {}
+                None => self.push(key, value),
+            }
+        }
+    }
+
+    /// Computes the changes needed to go from this file to `new`.
+    #[must_use]
+    pub fn diff(&self, new: &Self) -> Diff {
+        Diff::between(&self.to_map(), &new.to_map())
+    }
+
+    /// Only keeps keys for which `predicate` returns true.
+    #[must_use]
+    pub fn filter_keys<F: FnMut(&str) -> bool>(mut self, mut predicate: F) -> Self {
+        self.vars.retain(|(key, _)| predicate(key));
+        self.index = self
+            .vars
+            .iter()
+            .enumerate()
+            .map(|(position, (key, _))| (key.clone(), position))
+            .collect();
+        self
+    }
+
+    /// Only keeps keys that start with `prefix`.
+    #[must_use]
+    pub fn filter_prefix(self, prefix: &str) -> Self {
+        self.filter_keys(|key| key.starts_with(prefix))
+    }
+
+    /// Only keeps keys that match `pattern`, where `*` matches any run of characters and `?`
+    /// matches a single one.
+    #[must_use]
+    pub fn filter_glob(self, pattern: &str) -> Self {
+        self.filter_keys(|key| glob_match(pattern, key))
+    }
+
+    load_methods!(&self.pairs());
+
+    /// Writes the variables to `writer` as a .env file, one `KEY=value` line each, quoted as
+    /// `Formatter` would.
+    ///
+    /// Fails with `Error::InvalidKey` without writing anything if a key could not be read back,
+    /// such as one containing `=` or a line break.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        self.check_keys()?;
+        writer
+            .write_all(self.to_string().as_bytes())
+            .map_err(Error::Io)
+    }
+
+    /// Writes the variables to the file at `path`, replacing it, with the same checks as
+    /// `write`. On Unix, a new file is only readable and writable by its owner, as strict mode
+    /// requires.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.check_keys()?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path).map_err(Error::Io)?;
+        file.write_all(self.to_string().as_bytes())
+            .map_err(Error::Io)
+    }
+
+    fn check_keys(&self) -> Result<()> {
+        match self.vars.iter().find(|(key, _)| !parse::is_key(key)) {
+            Some((key, _)) => Err(Error::InvalidKey(key.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.index.get(key).copied()
+    }
+
+    fn push(&mut self, key: String, value: String) {
+        self.index.insert(key.clone(), self.vars.len());
+        self.vars.push((key, value));
+    }
+
+    fn pairs(&self) -> impl Iterator<Item = Result<(String, String)>> + '_ {
+        self.vars.iter().map(|pair| Ok(pair.clone()))
+    }
+
+    fn to_map(&self) -> HashMap<String, String> {
+        self.vars.iter().cloned().collect()
+    }
+}
+
+/// Parses a .env file from a string, without a path.
+impl FromStr for EnvFile {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Self::read(Iter::new(source.as_bytes()))
+    }
+}
+
+/// Writes the file, as `EnvFile::write` does, but without checking the keys.
+impl fmt::Display for EnvFile {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = String::new();
+        for (key, value) in &self.vars {
+            line.clear();
+            line.push_str(key);
+            line.push('=');
+            quote(&mut line, value);
+            writeln!(fmt, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for EnvFile {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut env = Self::new();
+        env.extend(iter);
+        env
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for EnvFile {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl IntoIterator for EnvFile {
+    type Item = (String, String);
+    type IntoIter = vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a EnvFile {
+    type Item = (&'a str, &'a str);
+    type IntoIter = EnvFileIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the variables of an `EnvFile`, in order.
+pub struct EnvFileIter<'a> {
+    inner: slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for EnvFileIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{lock_env, EnvGuard};
+
+    fn parse(source: &str) -> EnvFile {
+        let _lock = lock_env();
+        source.parse().unwrap()
+    }
+
+    fn keys(env: &EnvFile) -> Vec<&str> {
+        env.iter().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_parse_keeps_order() {
+        let env = parse("FILE_Z=1\nFILE_A=2\nFILE_M=${FILE_Z}3\nFILE_Z=again");
+
+        assert_eq!(keys(&env), ["FILE_Z", "FILE_A", "FILE_M"]);
+        assert_eq!(env.get("FILE_M"), Some("13"));
+        assert_eq!(env.get("FILE_Z"), Some("1"));
+        assert_eq!(env.path(), None);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut env: EnvFile = [("B", "1"), ("A", "2")].into_iter().collect();
+
+        assert_eq!(env.insert("B", "3"), Some("1".to_owned()));
+        assert_eq!(env.insert("C", "4"), None);
+        assert_eq!(env.remove("A"), Some("2".to_owned()));
+        assert_eq!(env.remove("A"), None);
+        assert_eq!(
+            env.into_iter().collect::<Vec<_>>(),
+            [
+                ("B".to_owned(), "3".to_owned()),
+                ("C".to_owned(), "4".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let base: EnvFile = [("HOST", "db"), ("PORT", "5432")].into_iter().collect();
+        let local: EnvFile = [("USER", "me"), ("PORT", "6543")].into_iter().collect();
+
+        let mut existing = base.clone();
+        existing.merge(local.clone(), Precedence::Existing);
+        assert_eq!(existing.to_string(), "HOST=db\nPORT=5432\nUSER=me\n");
+
+        let mut incoming = base;
+        incoming.merge(local, Precedence::Incoming);
+        assert_eq!(incoming.to_string(), "HOST=db\nPORT=6543\nUSER=me\n");
+    }
+
+    #[test]
+    fn test_diff_and_filter() {
+        let old: EnvFile = [("APP_KEPT", "1"), ("APP_OLD", "x"), ("OTHER", "y")]
+            .into_iter()
+            .collect();
+        let new: EnvFile = [("APP_KEPT", "2"), ("APP_NEW", "z"), ("OTHER", "y")]
+            .into_iter()
+            .collect();
+
+        let diff = old.diff(&new);
+        assert_eq!(keys(&diff.added.into_iter().collect()), ["APP_NEW"]);
+        assert_eq!(diff.changed["APP_KEPT"], ("1".to_owned(), "2".to_owned()));
+        assert!(diff.removed.contains_key("APP_OLD"));
+
+        assert_eq!(
+            keys(&new.clone().filter_prefix("APP_")),
+            ["APP_KEPT", "APP_NEW"]
+        );
+        assert_eq!(keys(&new.clone().filter_glob("*_NEW")), ["APP_NEW"]);
+        assert_eq!(keys(&new.filter_keys(|key| key.len() == 5)), ["OTHER"]);
+    }
+
+    #[test]
+    fn test_load() {
+        let _lock = lock_env();
+        let _existing = EnvGuard::capture(["FILE_LOAD_NEW", "FILE_LOAD_SET"]);
+        env::set_var("FILE_LOAD_SET", "kept");
+        let env: EnvFile = [("FILE_LOAD_NEW", "1"), ("FILE_LOAD_SET", "2")]
+            .into_iter()
+            .collect();
+
+        let guard = env.load_scoped().unwrap();
+        assert_eq!(env::var("FILE_LOAD_NEW").unwrap(), "1");
+        assert_eq!(env::var("FILE_LOAD_SET").unwrap(), "kept");
+        drop(guard);
+        assert!(env::var("FILE_LOAD_NEW").is_err());
+    }
+
+    #[test]
+    fn test_write_rejects_unreadable_keys() {
+        for key in ["A=B", "X\nINJECTED", "1KEY", ""] {
+            let mut env = EnvFile::new();
+            env.insert("VALID", "1");
+            env.insert(key, "y");
+
+            let mut output = Vec::new();
+            let err = env.write(&mut output).unwrap_err();
+            assert!(matches!(err, Error::InvalidKey(invalid) if invalid == key));
+            assert!(output.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_index_follows_removals() {
+        let mut env: EnvFile = [("A", "1"), ("B", "2"), ("C", "3"), ("D", "4")]
+            .into_iter()
+            .collect();
+
+        env.remove("B");
+        env = env.filter_keys(|key| key != "A");
+        env.insert("E", "5");
+
+        assert_eq!(keys(&env), ["C", "D", "E"]);
+        assert_eq!(env.get("D"), Some("4"));
+        assert_eq!(env.insert("C", "6"), Some("3".to_owned()));
+        assert_eq!(env.get("C"), Some("6"));
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        let env: EnvFile = [
+            ("FILE_PLAIN", "value"),
+            ("FILE_EMPTY", ""),
+            ("FILE_SPACES", "two words"),
+            ("FILE_DOLLAR", "$HOME"),
+            ("FILE_QUOTES", "it's \"quoted\"\nover lines"),
+        ]
+        .into_iter()
+        .collect();
+
+        env.save(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let read = {
+            let _lock = lock_env();
+            EnvFile::from_path(&path).unwrap()
+        };
+
+        assert_eq!(read.path(), Some(path.as_path()));
+        assert_eq!(
+            read.iter().collect::<Vec<_>>(),
+            env.iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -1,24 +1,61 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::find::Probe;
+#[cfg(feature = "strict")]
+use crate::strict::UnsafeReason;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Everything that can go wrong while finding, parsing or loading a file.
+///
+/// New variants may be added, some of them only with a feature, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    LineParse(String, usize),
+    LineParse(ParseError),
+    /// A key is defined a second time under `DuplicatePolicy::Error`, with the line numbers of
+    /// both definitions.
+    DuplicateKey(String, usize, usize),
+    /// A key is defined a second time under `DuplicatePolicy::Error` by another file included
+    /// as deeply as the first, with the path and line number of the first definition, then of
+    /// the second.
+    DuplicateIncludedKey(String, PathBuf, usize, PathBuf, usize),
+    /// An `# @include` directive includes a file that is already being read.
+    IncludeCycle(PathBuf),
+    /// `# @include` directives nest deeper than allowed by `Iter::max_include_depth`.
+    IncludeTooDeep(PathBuf),
+    /// A file read with non-blocking IO has an `# @include` directive for this path, which
+    /// cannot be followed without blocking.
+    AsyncInclude(PathBuf),
+    /// `Finder` found no file, with every path it probed and why each was rejected.
+    NotFound(Vec<Probe>),
+    /// Strict mode refused to load the file at this path.
+    #[cfg(feature = "strict")]
+    UnsafeFile(PathBuf, UnsafeReason),
     Io(io::Error),
     EnvVar(std::env::VarError),
+    /// The value of a variable could not be parsed into the requested type.
+    InvalidValue(String, Box<dyn error::Error + Send + Sync>),
+    /// A key cannot be written in the requested format.
+    InvalidKey(String),
+    /// A JSON, YAML or TOML document could not be parsed or flattened into variables.
+    InvalidDocument(Box<dyn error::Error + Send + Sync>),
+    #[cfg(feature = "watch")]
+    Watch(notify::Error),
 }
 
 impl Error {
     #[must_use]
     pub fn not_found(&self) -> bool {
-        if let Self::Io(ref io_error) = *self {
-            return io_error.kind() == io::ErrorKind::NotFound;
+        match self {
+            Self::NotFound(_) => true,
+            Self::Io(io_error) => io_error.kind() == io::ErrorKind::NotFound,
+            _ => false,
         }
-
-        false
     }
 }
 
@@ -27,7 +64,19 @@ impl error::Error for Error {
         match self {
             Self::Io(err) => Some(err),
             Self::EnvVar(err) => Some(err),
-            Self::LineParse(_, _) => None,
+            Self::InvalidValue(_, err) | Self::InvalidDocument(err) => Some(&**err),
+            #[cfg(feature = "watch")]
+            Self::Watch(err) => Some(err),
+            Self::LineParse(err) => Some(err),
+            Self::DuplicateKey(_, _, _)
+            | Self::DuplicateIncludedKey(_, _, _, _, _)
+            | Self::IncludeCycle(_)
+            | Self::IncludeTooDeep(_)
+            | Self::AsyncInclude(_)
+            | Self::NotFound(_)
+            | Self::InvalidKey(_) => None,
+            #[cfg(feature = "strict")]
+            Self::UnsafeFile(_, _) => None,
         }
     }
 }
@@ -37,12 +86,134 @@ impl fmt::Display for Error {
         match self {
             Self::Io(err) => write!(fmt, "{err}"),
             Self::EnvVar(err) => write!(fmt, "{err}"),
-            Self::LineParse(line, error_index) => write!(
+            Self::InvalidValue(key, err) => write!(fmt, "Invalid value for '{key}': {err}"),
+            Self::InvalidKey(key) => write!(fmt, "Invalid key '{}'", key.escape_debug()),
+            Self::InvalidDocument(err) => write!(fmt, "Invalid document: {err}"),
+            #[cfg(feature = "watch")]
+            Self::Watch(err) => write!(fmt, "{err}"),
+            Self::LineParse(err) => write!(fmt, "{err}"),
+            Self::DuplicateKey(key, first_line, second_line) => write!(
+                fmt,
+                "Duplicate key '{key}' on line {second_line}, first defined on line {first_line}"
+            ),
+            Self::DuplicateIncludedKey(key, first_path, first_line, second_path, second_line) => {
+                write!(
+                    fmt,
+                    "Duplicate key '{key}' on line {second_line} of '{}', first defined on line \
+                     {first_line} of '{}'",
+                    second_path.display(),
+                    first_path.display()
+                )
+            }
+            Self::IncludeCycle(path) => {
+                write!(fmt, "Cyclic include of '{}'", path.display())
+            }
+            Self::IncludeTooDeep(path) => {
+                write!(fmt, "Includes nested too deeply at '{}'", path.display())
+            }
+            Self::AsyncInclude(path) => write!(
                 fmt,
-                "Error parsing line: '{line}', error at line index: {error_index}"
+                "Cannot include '{}' in a file read asynchronously",
+                path.display()
             ),
+            #[cfg(feature = "strict")]
+            Self::UnsafeFile(path, reason) => {
+                write!(fmt, "Refusing to load '{}': {reason}", path.display())
+            }
+            Self::NotFound(probes) => {
+                fmt.write_str("File not found")?;
+                for (i, probe) in probes.iter().enumerate() {
+                    let separator = if i == 0 { ", probed " } else { ", " };
+                    write!(fmt, "{separator}{probe}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What went wrong while parsing a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The key is empty or starts with a character other than an ASCII letter or `_`.
+    InvalidKey,
+    /// The key is not followed by `=`.
+    MissingEquals,
+    /// A single or double quote is never closed.
+    UnterminatedQuote,
+    /// A backslash is followed by a character that cannot be escaped.
+    InvalidEscape,
+    /// An unquoted value is followed by something other than a comment.
+    UnexpectedCharacter,
+    /// A `${` substitution is never closed.
+    UnclosedSubstitution,
+    /// Valid shell syntax that `Dialect::shell_compat` does not support, such as command
+    /// substitution or parameter expansion with a default.
+    UnsupportedSyntax,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(match self {
+            Self::InvalidKey => "invalid key",
+            Self::MissingEquals => "missing '='",
+            Self::UnterminatedQuote => "unterminated quote",
+            Self::InvalidEscape => "invalid escape sequence",
+            Self::UnexpectedCharacter => "unexpected character",
+            Self::UnclosedSubstitution => "unclosed substitution",
+            Self::UnsupportedSyntax => "unsupported syntax",
+        })
+    }
+}
+
+/// A line that could not be parsed, along with what went wrong and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    line: String,
+    span: Range<usize>,
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: ParseErrorKind, line: &str, span: Range<usize>) -> Self {
+        Self {
+            kind,
+            line: line.to_owned(),
+            span,
         }
     }
+
+    #[must_use]
+    pub const fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    /// The line that failed to parse.
+    #[must_use]
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// The byte range within `line` that caused the error.
+    ///
+    /// The range is empty when something is missing at the end of the line.
+    #[must_use]
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "Error parsing line: '{}', {} at line index: {}",
+            self.line, self.kind, self.span.start
+        )
+    }
 }
 
 #[cfg(test)]
@@ -50,16 +221,13 @@ mod test {
     use std::error::Error as StdError;
 
     use super::*;
+    use crate::find::ProbeOutcome;
 
     #[test]
     fn test_io_error_source() {
-        let err = Error::Io(std::io::ErrorKind::PermissionDenied.into());
-        let io_err = err
-            .source()
-            .unwrap()
-            .downcast_ref::<std::io::Error>()
-            .unwrap();
-        assert_eq!(std::io::ErrorKind::PermissionDenied, io_err.kind());
+        let err = Error::Io(io::ErrorKind::PermissionDenied.into());
+        let io_err = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io::ErrorKind::PermissionDenied, io_err.kind());
     }
 
     #[test]
@@ -73,28 +241,64 @@ mod test {
         assert_eq!(&std::env::VarError::NotPresent, var_err);
     }
 
+    #[test]
+    fn test_invalidvalue_error_source() {
+        let parse_err = "x".parse::<u8>().unwrap_err();
+        let err = Error::InvalidValue("KEY".to_string(), Box::new(parse_err.clone()));
+        let source = err
+            .source()
+            .unwrap()
+            .downcast_ref::<std::num::ParseIntError>()
+            .unwrap();
+        assert_eq!(&parse_err, source);
+    }
+
     #[test]
     fn test_lineparse_error_source() {
-        let err = Error::LineParse("test line".to_string(), 2);
-        assert!(err.source().is_none());
+        let parse_err = ParseError::new(ParseErrorKind::MissingEquals, "test line", 2..3);
+        let err = Error::LineParse(parse_err.clone());
+        let source = err.source().unwrap().downcast_ref::<ParseError>().unwrap();
+        assert_eq!(&parse_err, source);
+        assert!(source.source().is_none());
     }
 
     #[test]
     fn test_error_not_found_true() {
-        let err = Error::Io(std::io::ErrorKind::NotFound.into());
+        let err = Error::Io(io::ErrorKind::NotFound.into());
         assert!(err.not_found());
     }
 
+    #[test]
+    fn test_error_not_found_probes() {
+        let err = Error::NotFound(vec![
+            Probe {
+                path: PathBuf::from("/app/.env"),
+                outcome: ProbeOutcome::NotAFile,
+            },
+            Probe {
+                path: PathBuf::from("/.env"),
+                outcome: ProbeOutcome::Missing,
+            },
+        ]);
+        assert!(err.not_found());
+        assert!(err.source().is_none());
+        assert_eq!(
+            err.to_string(),
+            "File not found, probed '/app/.env' (not a file), '/.env' (missing)"
+        );
+        assert_eq!(Error::NotFound(Vec::new()).to_string(), "File not found");
+    }
+
     #[test]
     fn test_error_not_found_false() {
-        let err = Error::Io(std::io::ErrorKind::PermissionDenied.into());
+        let err = Error::Io(io::ErrorKind::PermissionDenied.into());
         assert!(!err.not_found());
     }
 
     #[test]
     fn test_io_error_display() {
-        let err = Error::Io(std::io::ErrorKind::PermissionDenied.into());
-        let io_err: std::io::Error = std::io::ErrorKind::PermissionDenied.into();
+        let err = Error::Io(io::ErrorKind::PermissionDenied.into());
+        let io_err: io::Error = io::ErrorKind::PermissionDenied.into();
 
         let err_desc = format!("{err}");
         let io_err_desc = format!("{io_err}");
@@ -111,12 +315,56 @@ mod test {
         assert_eq!(var_err_desc, err_desc);
     }
 
+    #[test]
+    fn test_invalidvalue_error_display() {
+        let err = Error::InvalidValue("KEY".to_string(), Box::new("x".parse::<u8>().unwrap_err()));
+        assert_eq!(
+            "Invalid value for 'KEY': invalid digit found in string",
+            format!("{err}")
+        );
+    }
+
+    #[test]
+    fn test_invaliddocument_error_display() {
+        let err = Error::InvalidDocument(Box::from("top level is not a map"));
+        assert_eq!("Invalid document: top level is not a map", format!("{err}"));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_duplicatekey_error_display() {
+        let err = Error::DuplicateKey("KEY".to_string(), 1, 3);
+        assert_eq!(
+            "Duplicate key 'KEY' on line 3, first defined on line 1",
+            format!("{err}")
+        );
+    }
+
+    #[test]
+    fn test_duplicateincludedkey_error_display() {
+        let err = Error::DuplicateIncludedKey(
+            "KEY".to_string(),
+            PathBuf::from("a.env"),
+            1,
+            PathBuf::from("b.env"),
+            2,
+        );
+        assert_eq!(
+            "Duplicate key 'KEY' on line 2 of 'b.env', first defined on line 1 of 'a.env'",
+            format!("{err}")
+        );
+    }
+
     #[test]
     fn test_lineparse_error_display() {
-        let err = Error::LineParse("test line".to_string(), 2);
+        let err = Error::LineParse(ParseError::new(
+            ParseErrorKind::MissingEquals,
+            "test line",
+            4..5,
+        ));
         let err_desc = format!("{err}");
         assert_eq!(
-            "Error parsing line: 'test line', error at line index: 2",
+            "Error parsing line: 'test line', missing '=' at line index: 4",
             err_desc
         );
     }
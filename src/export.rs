@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::errors::{Error, Result};
+use crate::parse::is_shell_name;
+
+/// A format that variables can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Format {
+    /// A systemd `EnvironmentFile`, with every value in double quotes.
+    Systemd,
+    /// A file for `docker run --env-file`, which has no quoting at all, so values cannot
+    /// contain line breaks.
+    Docker,
+    /// A Kubernetes `ConfigMap` manifest.
+    ConfigMap,
+    /// A Kubernetes `Secret` manifest, with the values in `stringData`.
+    Secret,
+    /// A JSON object.
+    Json,
+    /// A shell script of `export` statements, with every value in single quotes.
+    Shell,
+}
+
+impl Format {
+    pub const ALL: [Self; 6] = [
+        Self::Systemd,
+        Self::Docker,
+        Self::ConfigMap,
+        Self::Secret,
+        Self::Json,
+        Self::Shell,
+    ];
+
+    /// The name of the format, as accepted by `from_str`.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Systemd => "systemd",
+            Self::Docker => "docker",
+            Self::ConfigMap => "configmap",
+            Self::Secret => "secret",
+            Self::Json => "json",
+            Self::Shell => "shell",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
+
+/// The error returned when parsing an unknown format name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownFormat(pub String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "unknown export format '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownFormat {}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| UnknownFormat(name.to_owned()))
+    }
+}
+
+/// Writes variables in another format.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+/// use dotenv::{Exporter, Format};
+///
+/// let iter = dotenv::from_str_iter(r#"GREETING="it's \"quoted\"""#);
+/// let script = Exporter::new(Format::Shell).to_string(iter).unwrap();
+///
+/// assert_eq!(script, "export GREETING='it'\\''s \"quoted\"'\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exporter {
+    format: Format,
+    name: String,
+}
+
+impl Exporter {
+    #[must_use]
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            name: String::from("dotenv"),
+        }
+    }
+
+    /// Sets the `metadata.name` of Kubernetes manifests. The default is `dotenv`.
+    #[must_use]
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Writes `pairs`, such as the items of an `Iter`, to `writer`.
+    ///
+    /// A key that occurs more than once is written once, with its last value. Nothing is
+    /// written if any item is an error, or if a key or value cannot be represented in the
+    /// format. The systemd, Docker and shell formats only accept keys that are valid shell
+    /// variable names, and fail with `Error::InvalidKey` for any other key.
+    pub fn write<W, I>(&self, mut writer: W, pairs: I) -> Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = Result<(String, String)>>,
+    {
+        let output = self.to_string(pairs)?;
+        writer.write_all(output.as_bytes()).map_err(Error::Io)
+    }
+
+    /// Like `write`, but returns the output as a string.
+    pub fn to_string<I>(&self, pairs: I) -> Result<String>
+    where
+        I: IntoIterator<Item = Result<(String, String)>>,
+    {
+        let mut unique: Vec<(String, String)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for item in pairs {
+            let (key, value) = item?;
+            if let Some(&position) = positions.get(&key) {
+                unique[position].1 = value;
+            } else {
+                positions.insert(key.clone(), unique.len());
+                unique.push((key, value));
+            }
+        }
+
+        if matches!(
+            self.format,
+            Format::Systemd | Format::Docker | Format::Shell
+        ) {
+            if let Some((key, _)) = unique.iter().find(|(key, _)| !is_shell_name(key)) {
+                return Err(Error::InvalidKey(key.clone()));
+            }
+        }
+
+        let mut output = String::new();
+        match self.format {
+            Format::Systemd => {
+                for (key, value) in &unique {
+                    let _ = writeln!(output, "{key}={}", double_quote(value));
+                }
+            }
+            Format::Docker => {
+                for (key, value) in &unique {
+                    if value.contains(['\n', '\r']) {
+                        let err = io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "docker env files cannot contain line breaks",
+                        );
+                        return Err(Error::InvalidValue(key.clone(), Box::new(err)));
+                    }
+                    let _ = writeln!(output, "{key}={value}");
+                }
+            }
+            Format::ConfigMap | Format::Secret => {
+                let (kind, data) = match self.format {
+                    Format::Secret => ("Secret\ntype: Opaque", "stringData"),
+                    _ => ("ConfigMap", "data"),
+                };
+                let _ = write!(
+                    output,
+                    "apiVersion: v1\nkind: {kind}\nmetadata:\n  name: {}\n{data}:",
+                    json_string(&self.name),
+                );
+                if unique.is_empty() {
+                    output.push_str(" {}");
+                }
+                output.push('\n');
+                for (key, value) in &unique {
+                    let _ = writeln!(output, "  {}: {}", json_string(key), json_string(value));
+                }
+            }
+            Format::Json => {
+                output.push('{');
+                for (index, (key, value)) in unique.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { "," };
+                    let _ = write!(
+                        output,
+                        "{separator}\n  {}: {}",
+                        json_string(key),
+                        json_string(value)
+                    );
+                }
+                output.push_str(if unique.is_empty() { "}\n" } else { "\n}\n" });
+            }
+            Format::Shell => {
+                for (key, value) in &unique {
+                    let _ = writeln!(output, "export {key}='{}'", value.replace('\'', r"'\''"));
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Quotes `value` for a systemd `EnvironmentFile`, where a backslash escapes `"`, `\`, `` ` ``
+/// and `$` within double quotes.
+fn double_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '`' | '$') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes `value` as a JSON string, which is also a valid double quoted YAML scalar.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str(r#"\""#),
+            '\\' => quoted.push_str(r"\\"),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn export(format: Format, input: &[(&str, &str)]) -> Result<String> {
+        let pairs = input
+            .iter()
+            .map(|(key, value)| Ok(((*key).to_string(), (*value).to_string())));
+        Exporter::new(format).to_string(pairs)
+    }
+
+    const PAIRS: &[(&str, &str)] = &[
+        ("PLAIN", "value"),
+        ("TRICKY", "it's \"$HOME\" \\ `x`"),
+        ("PLAIN", "last"),
+    ];
+
+    #[test]
+    fn test_format_names_round_trip() {
+        for format in Format::ALL {
+            assert_eq!(format.name().parse::<Format>().unwrap(), format);
+        }
+        assert_eq!(
+            "yaml".parse::<Format>().unwrap_err().to_string(),
+            "unknown export format 'yaml'"
+        );
+    }
+
+    #[test]
+    fn test_systemd() {
+        assert_eq!(
+            export(Format::Systemd, PAIRS).unwrap(),
+            "PLAIN=\"last\"\nTRICKY=\"it's \\\"\\$HOME\\\" \\\\ \\`x\\`\"\n"
+        );
+    }
+
+    #[test]
+    fn test_docker() {
+        assert_eq!(
+            export(Format::Docker, PAIRS).unwrap(),
+            "PLAIN=last\nTRICKY=it's \"$HOME\" \\ `x`\n"
+        );
+
+        let err = export(Format::Docker, &[("MULTI", "a\nb")]).unwrap_err();
+        assert!(matches!(err, Error::InvalidValue(key, _) if key == "MULTI"));
+    }
+
+    #[test]
+    fn test_kubernetes() {
+        let pairs = [("KEY", "line\nbreak \u{7}")];
+
+        assert_eq!(
+            export(Format::ConfigMap, &pairs).unwrap(),
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: \"dotenv\"\ndata:\n  \"KEY\": \"line\\nbreak \\u0007\"\n"
+        );
+        let secret = Exporter::new(Format::Secret)
+            .name("app")
+            .to_string(Vec::new())
+            .unwrap();
+        assert_eq!(
+            secret,
+            "apiVersion: v1\nkind: Secret\ntype: Opaque\nmetadata:\n  name: \"app\"\nstringData: {}\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            export(Format::Json, PAIRS).unwrap(),
+            "{\n  \"PLAIN\": \"last\",\n  \"TRICKY\": \"it's \\\"$HOME\\\" \\\\ `x`\"\n}\n"
+        );
+        assert_eq!(export(Format::Json, &[]).unwrap(), "{}\n");
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(
+            export(Format::Shell, PAIRS).unwrap(),
+            "export PLAIN='last'\nexport TRICKY='it'\\''s \"$HOME\" \\ `x`'\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        for format in [Format::Systemd, Format::Docker, Format::Shell] {
+            for key in ["a;touch${IFS}pwned;b", "A.B", "1KEY", "KEY NAME", ""] {
+                let err = export(format, &[("VALID", "1"), (key, "v")]).unwrap_err();
+                assert!(matches!(err, Error::InvalidKey(invalid) if invalid == key));
+            }
+            assert!(export(format, &[("_valid_1", "v")]).is_ok());
+        }
+        assert_eq!(
+            export(Format::Json, &[("A.B", "v")]).unwrap(),
+            "{\n  \"A.B\": \"v\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_are_propagated() {
+        let pairs = crate::from_str_iter("KEY=1\nKEY2=\"unterminated");
+
+        assert!(Exporter::new(Format::Json).to_string(pairs).is_err());
+    }
+}
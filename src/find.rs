@@ -1,58 +1,539 @@
-use std::fs::File;
+use std::fmt;
+use std::fs::{File, Metadata};
+#[cfg(feature = "async")]
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 use std::{env, fs, io};
 
 use crate::errors::{Error, Result};
+use crate::instrument::event;
 use crate::iter::Iter;
+#[cfg(feature = "strict")]
+use crate::strict;
 
+/// Searches for .env files in the current directory, its parents, and other directories.
+///
+/// Each directory is searched for a list of candidate names, in order. The current directory
+/// and its parents are searched up to the first one that contains any candidate, like a
+/// project root, after which the extra directories are searched in the order they were added.
+/// Matches are returned in that order of precedence, most specific first.
+///
+/// If nothing is found, `find` fails with `Error::NotFound`, which lists every path that was
+/// probed and why it was rejected. `probe` returns the same list whether or not anything is
+/// found.
+///
+/// In strict mode, a match that is not safe to load secrets from, as described in
+/// `open_checked`, fails the search with `Error::UnsafeFile`.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv::{self as dotenv, Finder};
+/// use std::path::Path;
+///
+/// // .env.local and .env of the project, then user and system defaults
+/// let user = dirs_config().join("app");
+/// let paths = Finder::new()
+///     .filenames([".env.local", ".env"])
+///     .directory(&user)
+///     .directory(Path::new("/etc/app"))
+///     .find_all()
+///     .unwrap();
+///
+/// // variables that are already set are kept, so earlier files take precedence
+/// for path in paths {
+///     dotenv::from_path(path).unwrap();
+/// }
+/// # fn dirs_config() -> std::path::PathBuf { std::path::PathBuf::from("/home/me/.config") }
+/// ```
+#[derive(Debug, Clone)]
 pub struct Finder<'a> {
-    filename: &'a Path,
+    filenames: Vec<&'a Path>,
+    directories: Vec<&'a Path>,
+    #[cfg(feature = "strict")]
+    strict: bool,
+}
+
+impl Default for Finder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Finder<'a> {
+    /// Creates a finder for `.env` in the current directory and its parents.
+    #[must_use]
     pub fn new() -> Self {
         Finder {
-            filename: Path::new(".env"),
+            filenames: vec![Path::new(".env")],
+            directories: Vec::new(),
+            #[cfg(feature = "strict")]
+            strict: false,
         }
     }
 
-    pub const fn filename(mut self, filename: &'a Path) -> Self {
-        self.filename = filename;
+    /// Searches for `filename` instead of `.env`.
+    #[must_use]
+    pub fn filename(mut self, filename: &'a Path) -> Self {
+        self.filenames = vec![filename];
+        self
+    }
+
+    /// Searches for each of `filenames` instead of `.env`, in order of precedence.
+    #[must_use]
+    pub fn filenames<I, P>(mut self, filenames: I) -> Self
+    where
+        I: IntoIterator<Item = &'a P>,
+        P: AsRef<Path> + ?Sized + 'a,
+    {
+        self.filenames = filenames.into_iter().map(AsRef::as_ref).collect();
+        self
+    }
+
+    /// Also searches `directory`, after the current directory and its parents and any
+    /// directories added before. Its parents are not searched.
+    #[must_use]
+    pub fn directory<P: AsRef<Path> + ?Sized>(mut self, directory: &'a P) -> Self {
+        self.directories.push(directory.as_ref());
+        self
+    }
+
+    /// Refuses matches that are unsafe to load secrets from, which is off by default.
+    #[cfg(feature = "strict")]
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
         self
     }
 
+    /// Returns the first match and an iterator over its variables.
+    ///
+    /// In strict mode, the variables are read from the same handle that was checked, and
+    /// `# @include` targets are checked as well, see `Iter::strict`.
     pub fn find(self) -> Result<(PathBuf, Iter<File>)> {
-        let path = find(&env::current_dir().map_err(Error::Io)?, self.filename)?;
-        let file = File::open(&path).map_err(Error::Io)?;
-        let iter = Iter::new(file);
+        let (probes, file) = self.search()?;
+        let path = first_match(probes)?;
+        let file = match file {
+            Some(file) => file,
+            None => File::open(&path).map_err(Error::Io)?,
+        };
+        let iter = Iter::new(file).with_path(&path);
+        #[cfg(feature = "strict")]
+        let iter = iter.strict(self.strict);
         Ok((path, iter))
     }
-}
 
-/// Searches for `filename` in `directory` and parent directories until found or root is reached.
-pub fn find(directory: &Path, filename: &Path) -> Result<PathBuf> {
-    let candidate = directory.join(filename);
+    /// Returns every match, in order of precedence, which is empty if there are none.
+    pub fn find_all(&self) -> Result<Vec<PathBuf>> {
+        Ok(matches(self.probe()?))
+    }
+
+    /// Returns every path that was probed, in order, with what was found there.
+    pub fn probe(&self) -> Result<Vec<Probe>> {
+        Ok(self.search()?.0)
+    }
+
+    /// Probes every candidate, returning the probes and, in strict mode, the first match as
+    /// opened by `open_checked`.
+    fn search(&self) -> Result<(Vec<Probe>, Option<File>)> {
+        let current_dir = env::current_dir().map_err(Error::Io)?;
+        let mut probes = Vec::new();
+        let mut seen = Vec::new();
+        let mut file = None;
+        for directory in current_dir.ancestors() {
+            file = file.or(self.probe_directory(directory, &mut probes, &mut seen)?);
+            if probes.iter().any(Probe::is_match) {
+                break;
+            }
+        }
+        for directory in &self.directories {
+            file = file.or(self.probe_directory(directory, &mut probes, &mut seen)?);
+        }
+        for probe in &probes {
+            event!(trace, "probed {probe}");
+        }
+        Ok((probes, file))
+    }
+
+    /// Probes each candidate name in `directory`, recording the canonical paths of matches in
+    /// `seen` so that later candidates resolving to them are duplicates. In strict mode, returns
+    /// the first match as opened by `open_checked`.
+    fn probe_directory(
+        &self,
+        directory: &Path,
+        probes: &mut Vec<Probe>,
+        seen: &mut Vec<PathBuf>,
+    ) -> Result<Option<File>> {
+        #[cfg_attr(not(feature = "strict"), allow(unused_mut))]
+        let mut file = None;
+        for filename in &self.filenames {
+            let candidate = directory.join(filename);
+            let canonical = fs::canonicalize(&candidate).ok();
+            let outcome = outcome(fs::metadata(&candidate), canonical, seen)?;
+            #[cfg(feature = "strict")]
+            if self.strict && outcome == ProbeOutcome::Found {
+                let checked = strict::open_checked(&candidate, directory)?;
+                file = file.or(Some(checked));
+            }
+            probes.push(Probe::new(candidate, outcome));
+        }
+        Ok(file)
+    }
+
+    /// Like `find`, but probes and reads the file with non-blocking IO.
+    ///
+    /// The whole file is read into memory, so the returned iterator never blocks. For the same
+    /// reason, `# @include` directives fail with `Error::AsyncInclude`.
+    #[cfg(feature = "async")]
+    pub async fn find_async(self) -> Result<(PathBuf, Iter<Cursor<Vec<u8>>>)> {
+        use tokio::io::AsyncReadExt;
+
+        let (probes, file) = self.search_async().await?;
+        let path = first_match(probes)?;
+        let contents = match file {
+            Some(mut file) => {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents).await.map_err(Error::Io)?;
+                contents
+            }
+            None => tokio::fs::read(&path).await.map_err(Error::Io)?,
+        };
+        let iter = Iter::new(Cursor::new(contents)).reject_includes();
+        Ok((path, iter))
+    }
+
+    /// Like `find_all`, but uses non-blocking metadata lookups.
+    #[cfg(feature = "async")]
+    pub async fn find_all_async(&self) -> Result<Vec<PathBuf>> {
+        Ok(matches(self.probe_async().await?))
+    }
+
+    /// Like `probe`, but uses non-blocking metadata lookups.
+    #[cfg(feature = "async")]
+    pub async fn probe_async(&self) -> Result<Vec<Probe>> {
+        Ok(self.search_async().await?.0)
+    }
 
-    match fs::metadata(&candidate) {
-        Ok(metadata) => {
-            if metadata.is_file() {
-                return Ok(candidate);
+    /// Like `search`, but uses non-blocking metadata lookups.
+    #[cfg(feature = "async")]
+    async fn search_async(&self) -> Result<(Vec<Probe>, Option<tokio::fs::File>)> {
+        let current_dir = env::current_dir().map_err(Error::Io)?;
+        let mut probes = Vec::new();
+        let mut seen = Vec::new();
+        let mut file = None;
+        for directory in current_dir.ancestors() {
+            let checked = self
+                .probe_directory_async(directory, &mut probes, &mut seen)
+                .await?;
+            file = file.or(checked);
+            if probes.iter().any(Probe::is_match) {
+                break;
             }
         }
-        Err(error) => {
-            if error.kind() != io::ErrorKind::NotFound {
-                return Err(Error::Io(error));
+        for directory in &self.directories {
+            let checked = self
+                .probe_directory_async(directory, &mut probes, &mut seen)
+                .await?;
+            file = file.or(checked);
+        }
+        for probe in &probes {
+            event!(trace, "probed {probe}");
+        }
+        Ok((probes, file))
+    }
+
+    /// Like `probe_directory`, but uses non-blocking metadata lookups.
+    #[cfg(feature = "async")]
+    async fn probe_directory_async(
+        &self,
+        directory: &Path,
+        probes: &mut Vec<Probe>,
+        seen: &mut Vec<PathBuf>,
+    ) -> Result<Option<tokio::fs::File>> {
+        #[cfg_attr(not(feature = "strict"), allow(unused_mut))]
+        let mut file = None;
+        for filename in &self.filenames {
+            let candidate = directory.join(filename);
+            let metadata = tokio::fs::metadata(&candidate).await;
+            let canonical = tokio::fs::canonicalize(&candidate).await.ok();
+            let outcome = outcome(metadata, canonical, seen)?;
+            #[cfg(feature = "strict")]
+            if self.strict && outcome == ProbeOutcome::Found {
+                let checked = strict::open_checked_async(&candidate, directory).await?;
+                file = file.or(Some(checked));
             }
+            probes.push(Probe::new(candidate, outcome));
         }
+        Ok(file)
+    }
+}
+
+/// A path that a `Finder` probed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub path: PathBuf,
+    pub outcome: ProbeOutcome,
+}
+
+impl Probe {
+    const fn new(path: PathBuf, outcome: ProbeOutcome) -> Self {
+        Self { path, outcome }
     }
 
-    directory.parent().map_or_else(
-        || {
-            Err(Error::Io(io::Error::new(
-                io::ErrorKind::NotFound,
-                "path not found",
-            )))
+    /// Whether the path was used, which is the case unless it was rejected.
+    #[must_use]
+    pub fn is_match(&self) -> bool {
+        self.outcome == ProbeOutcome::Found
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "'{}' ({})", self.path.display(), self.outcome)
+    }
+}
+
+/// What a `Finder` found at a path, and so why it was used or rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProbeOutcome {
+    /// A file, which is a match.
+    Found,
+    /// Nothing exists at the path.
+    Missing,
+    /// Something other than a file, such as a directory.
+    NotAFile,
+    /// The path could not be accessed.
+    PermissionDenied,
+    /// The same file as an earlier match, possibly under another name.
+    Duplicate,
+}
+
+impl fmt::Display for ProbeOutcome {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(match self {
+            Self::Found => "found",
+            Self::Missing => "missing",
+            Self::NotAFile => "not a file",
+            Self::PermissionDenied => "permission denied",
+            Self::Duplicate => "duplicate of an earlier match",
+        })
+    }
+}
+
+/// Classifies a candidate from its metadata and canonical path, recording the canonical paths
+/// of matches in `seen` so that a file found again under another name is a duplicate.
+fn outcome(
+    metadata: io::Result<Metadata>,
+    canonical: Option<PathBuf>,
+    seen: &mut Vec<PathBuf>,
+) -> Result<ProbeOutcome> {
+    match metadata {
+        Ok(metadata) if !metadata.is_file() => Ok(ProbeOutcome::NotAFile),
+        Ok(_) => match canonical {
+            Some(canonical) if seen.contains(&canonical) => Ok(ProbeOutcome::Duplicate),
+            Some(canonical) => {
+                seen.push(canonical);
+                Ok(ProbeOutcome::Found)
+            }
+            None => Ok(ProbeOutcome::Found),
+        },
+        Err(error) => match error.kind() {
+            io::ErrorKind::NotFound => Ok(ProbeOutcome::Missing),
+            io::ErrorKind::PermissionDenied => Ok(ProbeOutcome::PermissionDenied),
+            _ => Err(Error::Io(error)),
         },
-        |parent| find(parent, filename),
-    )
+    }
+}
+
+fn matches(probes: Vec<Probe>) -> Vec<PathBuf> {
+    let found: Vec<PathBuf> = probes
+        .into_iter()
+        .filter(Probe::is_match)
+        .map(|probe| probe.path)
+        .collect();
+    for path in &found {
+        event!(debug, "found '{}'", path.display());
+    }
+    found
+}
+
+fn first_match(mut probes: Vec<Probe>) -> Result<PathBuf> {
+    if let Some(index) = probes.iter().position(Probe::is_match) {
+        let path = probes.swap_remove(index).path;
+        event!(debug, "found '{}'", path.display());
+        Ok(path)
+    } else {
+        let err = Error::NotFound(probes);
+        event!(debug, "{err}");
+        Err(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lock_env;
+
+    #[test]
+    fn test_find_all() {
+        let _lock = lock_env();
+        let project = tempfile::TempDir::new().unwrap();
+        let system = tempfile::TempDir::new().unwrap();
+        let child = project.path().join("child");
+        fs::create_dir(&child).unwrap();
+        for path in [
+            project.path().join(".env"),
+            project.path().join(".env.local"),
+            system.path().join(".env"),
+        ] {
+            fs::write(path, "").unwrap();
+        }
+        // a directory with a candidate name is not a match
+        fs::create_dir(child.join(".env.local")).unwrap();
+        env::set_current_dir(&child).unwrap();
+
+        let found = Finder::new()
+            .filenames([".env.local", ".env"])
+            .directory(system.path())
+            .directory(project.path())
+            .find_all();
+        let first = Finder::new().filenames(&[".env.local"]).find();
+        let missing = Finder::new().filename(Path::new("missing.env")).find();
+
+        let found: Vec<PathBuf> = found
+            .unwrap()
+            .into_iter()
+            .map(|path| path.canonicalize().unwrap())
+            .collect();
+        let project = project.path().canonicalize().unwrap();
+        assert_eq!(
+            found,
+            [
+                project.join(".env.local"),
+                project.join(".env"),
+                system.path().canonicalize().unwrap().join(".env"),
+            ]
+        );
+        assert_eq!(
+            first.unwrap().0.canonicalize().unwrap(),
+            project.join(".env.local")
+        );
+        assert!(matches!(missing, Err(err) if err.not_found()));
+    }
+
+    #[test]
+    fn test_probe() {
+        let _lock = lock_env();
+        let project = tempfile::TempDir::new().unwrap();
+        let child = project.path().join("child");
+        fs::create_dir(&child).unwrap();
+        fs::create_dir(child.join(".env")).unwrap();
+        fs::write(project.path().join(".env"), "").unwrap();
+        env::set_current_dir(&child).unwrap();
+
+        let probes = Finder::new()
+            .filenames([".env.local", ".env"])
+            .directory(project.path())
+            .probe();
+        let missing = Finder::new().filename(Path::new("missing.env")).find();
+
+        let outcomes: Vec<ProbeOutcome> = probes
+            .unwrap()
+            .into_iter()
+            .map(|probe| probe.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ProbeOutcome::Missing,
+                ProbeOutcome::NotAFile,
+                ProbeOutcome::Missing,
+                ProbeOutcome::Found,
+                ProbeOutcome::Missing,
+                ProbeOutcome::Duplicate,
+            ]
+        );
+        match missing {
+            Err(Error::NotFound(probes)) => {
+                assert!(!probes.is_empty());
+                assert!(probes
+                    .iter()
+                    .all(|probe| probe.outcome == ProbeOutcome::Missing));
+                assert!(probes[0].path.ends_with("child/missing.env"));
+            }
+            _ => panic!("expected Error::NotFound"),
+        }
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "strict"))]
+    fn test_strict() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _lock = lock_env();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        env::set_current_dir(&dir).unwrap();
+
+        let lenient = Finder::new().find_all();
+        let refused = Finder::new().strict(true).find();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let accepted = Finder::new().strict(true).find();
+
+        assert_eq!(lenient.unwrap().len(), 1);
+        assert!(matches!(refused, Err(Error::UnsafeFile(_, _))));
+        assert!(accepted.is_ok());
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "strict"))]
+    fn test_strict_includes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::UnsafeReason;
+
+        let write = |path: &Path, contents: &str, mode: u32| {
+            fs::write(path, contents).unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        let _lock = lock_env();
+        let root = tempfile::TempDir::new().unwrap();
+        let project = root.path().join("project");
+        let outside = root.path().join("outside");
+        fs::create_dir(&project).unwrap();
+        fs::create_dir(&outside).unwrap();
+        write(&outside.join("secret.env"), "STRICT_OUTSIDE=1", 0o666);
+        write(&project.join("shared.env"), "STRICT_SHARED=1", 0o666);
+        env::set_current_dir(&project).unwrap();
+
+        let include = |target: &str| {
+            write(
+                &project.join(".env"),
+                &format!("# @include {target}"),
+                0o600,
+            );
+            let (_, iter) = Finder::new().strict(true).find().unwrap();
+            iter.collect::<Result<Vec<_>>>()
+        };
+        let escaped = include("../outside/secret.env");
+        let writable = include("shared.env");
+        fs::set_permissions(
+            project.join("shared.env"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        let accepted = include("shared.env");
+
+        assert!(matches!(
+            escaped,
+            Err(Error::UnsafeFile(_, UnsafeReason::OutsideDirectory(_)))
+        ));
+        assert!(matches!(
+            writable,
+            Err(Error::UnsafeFile(_, UnsafeReason::WorldWritable(_)))
+        ));
+        assert_eq!(accepted.unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,288 @@
+use std::fmt::Write as _;
+
+use crate::entry::{Entry, EntryIter};
+use crate::errors::Result;
+
+/// Formats a .env file with the default `Formatter`.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+///
+/// let formatted = dotenv::fmt("# Database\nexport PORT = \"5432\"\nHOST= 'db' \n\n\n").unwrap();
+///
+/// assert_eq!(formatted, "# Database\nHOST=db\nexport PORT=5432\n");
+/// ```
+pub fn fmt(source: &str) -> Result<String> {
+    Formatter::new().format(source)
+}
+
+/// Canonicalizes the style of .env files.
+///
+/// Entries are written as `KEY=value` without spaces around the `=`, and values are quoted
+/// consistently: not at all if that is unambiguous, in single quotes if possible, and in double
+/// quotes with escapes otherwise. Values with `$NAME` or `${NAME}` references are kept as
+/// written. Within each section, a run of entries between comments and blank lines, keys are
+/// sorted. Comments are kept, indentation and trailing whitespace are removed, and blank lines
+/// are collapsed.
+///
+/// The formatted file always parses with `Iter` to the same pairs as the original. A section
+/// whose values refer to keys defined in the same section is not sorted, because that could
+/// change what the references see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formatter {
+    remove_export: bool,
+    sort_keys: bool,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A line of the source, as far as formatting is concerned.
+enum Line<'a> {
+    Blank,
+    Comment(&'a str),
+    Entry {
+        export: bool,
+        entry: Box<Entry>,
+        comment: Option<&'a str>,
+    },
+}
+
+impl Formatter {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            remove_export: false,
+            sort_keys: true,
+        }
+    }
+
+    /// Removes `export` prefixes, which are kept by default.
+    #[must_use]
+    pub const fn remove_export(mut self, remove_export: bool) -> Self {
+        self.remove_export = remove_export;
+        self
+    }
+
+    /// Sorts the keys of each section, which is the default.
+    #[must_use]
+    pub const fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Formats `source`, failing with the first line that does not parse.
+    pub fn format(&self, source: &str) -> Result<String> {
+        let mut lines = lines(source)?;
+        if self.sort_keys {
+            for section in lines.split_mut(|line| !matches!(line, Line::Entry { .. })) {
+                if is_sortable(section) {
+                    section.sort_by(|a, b| key(a).cmp(key(b)));
+                }
+            }
+        }
+
+        let mut output = String::with_capacity(source.len());
+        let mut blank = false;
+        for line in lines {
+            match line {
+                Line::Blank => {
+                    blank = !output.is_empty();
+                    continue;
+                }
+                _ if blank => {
+                    output.push('\n');
+                    blank = false;
+                }
+                _ => {}
+            }
+
+            match line {
+                Line::Blank => {}
+                Line::Comment(comment) => output.push_str(comment),
+                Line::Entry {
+                    export,
+                    entry,
+                    comment,
+                } => {
+                    if export && !self.remove_export {
+                        output.push_str("export ");
+                    }
+                    output.push_str(&entry.key);
+                    output.push('=');
+                    if entry.references.is_empty() {
+                        quote(&mut output, &entry.value);
+                    } else {
+                        output.push_str(&entry.raw_value);
+                    }
+                    if let Some(comment) = comment {
+                        output.push(' ');
+                        output.push_str(comment);
+                    }
+                }
+            }
+            output.push('\n');
+        }
+        Ok(output)
+    }
+}
+
+/// Splits `source` into lines, with the entries that are defined on them.
+fn lines(source: &str) -> Result<Vec<Line<'_>>> {
+    let mut entries = EntryIter::new(source).peekable();
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < source.len() {
+        let end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |end| offset + end);
+        let text = &source[offset..end];
+
+        // an error is returned as soon as it comes up
+        let next = entries.next_if(|item| {
+            item.as_ref()
+                .map_or(true, |entry| entry.key_span.bytes.start < end)
+        });
+        lines.push(match next {
+            Some(entry) => {
+                let entry = entry?;
+                let comment = source[entry.value_span.bytes.end..end].trim();
+                Line::Entry {
+                    export: !source[offset..entry.key_span.bytes.start].trim().is_empty(),
+                    comment: (!comment.is_empty()).then_some(comment),
+                    entry: Box::new(entry),
+                }
+            }
+            None if text.trim().is_empty() => Line::Blank,
+            None => Line::Comment(text.trim()),
+        });
+        offset = end + 1;
+    }
+    Ok(lines)
+}
+
+fn key<'a>(line: &'a Line<'_>) -> &'a str {
+    match line {
+        Line::Entry { entry, .. } => &entry.key,
+        _ => "",
+    }
+}
+
+/// Whether sorting `section` keeps the values of its entries, which it does unless one of
+/// them refers to a key defined in the section.
+fn is_sortable(section: &[Line<'_>]) -> bool {
+    let mut references = section.iter().flat_map(|line| match line {
+        Line::Entry { entry, .. } => entry.references.as_slice(),
+        _ => &[],
+    });
+    references.all(|reference| section.iter().all(|line| key(line) != reference.name))
+}
+
+/// Writes `value` in the simplest quoting that parses back to it.
+pub fn quote(output: &mut String, value: &str) {
+    let plain = |c: char| !(c.is_whitespace() || c.is_control() || "'\"\\$#".contains(c));
+    if value.chars().all(plain) {
+        output.push_str(value);
+    } else if !value.contains(|c: char| c == '\'' || c.is_control()) {
+        let _ = write!(output, "'{value}'");
+    } else {
+        output.push('"');
+        for c in value.chars() {
+            match c {
+                '\\' | '"' | '$' => {
+                    output.push('\\');
+                    output.push(c);
+                }
+                '\n' => output.push_str(r"\n"),
+                c => output.push(c),
+            }
+        }
+        output.push('"');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{iter::Iter, lock_env};
+
+    fn pairs(source: &str) -> Vec<(String, String)> {
+        let mut pairs: Vec<_> = Iter::new(source.as_bytes()).collect::<Result<_>>().unwrap();
+        pairs.sort();
+        pairs
+    }
+
+    const SOURCES: &[&str] = &[
+        "",
+        "\n\n",
+        "FMT_A=1",
+        "  # indented comment   \n\n\n\nFMT_B = 2\nFMT_A= 1 # trailing\n\n",
+        "export FMT_A=\"double\"\nFMT_B='single'\nFMT_C=a\\ b\nexport=key",
+        "FMT_QUOTES=\"it's \\\"quoted\\\"\"\nFMT_DOLLAR='$HOME'\nFMT_HASH=a#b\nFMT_NL=\"a\\nb\"",
+        "FMT_EMPTY=\nFMT_EMPTY_QUOTED=''\nFMT_SPACE=' '\nFMT_BACKSLASH='\\'",
+        "FMT_Z=${FMT_A}\nFMT_A=1\n\nFMT_Y=$FMT_A\nFMT_B=2",
+        "FMT_DUP=1\nFMT_DUP=2\nFMT_C=3\n# @include other.env\nFMT_B=4",
+        "FMT_UNICODE='ünï cödé'\nFMT_MIXED='a'\"b\"c",
+    ];
+
+    #[test]
+    fn test_preserves_pairs() {
+        let _lock = lock_env();
+        for source in SOURCES {
+            for formatter in [
+                Formatter::new(),
+                Formatter::new().sort_keys(false).remove_export(true),
+            ] {
+                let formatted = formatter.format(source).unwrap();
+                assert_eq!(pairs(&formatted), pairs(source), "{formatted}");
+                assert_eq!(formatter.format(&formatted).unwrap(), formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_style() {
+        let _lock = lock_env();
+        let source = "\n\n  # header  \nexport FMT_B = \"two words\"   # note\nFMT_A='plain'\n\n\n# refs\nFMT_D=${FMT_C}\nFMT_C=\"it's\"\nFMT_E=\"$FMT_A\"\n\n";
+
+        assert_eq!(
+            fmt(source).unwrap(),
+            "# header\nFMT_A=plain\nexport FMT_B='two words' # note\n\n# refs\nFMT_D=${FMT_C}\nFMT_C=\"it's\"\nFMT_E=\"$FMT_A\"\n"
+        );
+        assert_eq!(
+            Formatter::new()
+                .remove_export(true)
+                .sort_keys(false)
+                .format("export FMT_B=1\nFMT_A=2")
+                .unwrap(),
+            "FMT_B=1\nFMT_A=2\n"
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        for (value, expected) in [
+            ("plain-value/1.0:x@y", "plain-value/1.0:x@y"),
+            ("", ""),
+            ("two words", "'two words'"),
+            ("$HOME#x", "'$HOME#x'"),
+            ("it's", "\"it's\""),
+            ("a\nb \"$c\" \\", r#""a\nb \"\$c\" \\""#),
+        ] {
+            let mut output = String::new();
+            quote(&mut output, value);
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let _lock = lock_env();
+        assert!(fmt("FMT_OK=1\nFMT_BAD=a b").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+//! Events about what loading did, emitted through `log` and `tracing` when those features are
+//! enabled. Values are never included, since .env files usually hold secrets.
+
+/// Emits an event at `$level` (`trace`, `debug`, `info`, `warn` or `error`) with a message
+/// formatted like `format!`, or only type checks the message if no backend is enabled.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)+);
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+pub(crate) use event;
+
+#[cfg(test)]
+#[cfg(feature = "log")]
+pub mod test {
+    use std::path::Path;
+    use std::sync::{Mutex, Once, PoisonError};
+    use std::{env, fs};
+
+    use tempfile::TempDir;
+
+    use crate::{lock_env, EnvGuard, Finder, Iter};
+
+    static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Recorder;
+
+    impl log::Log for Recorder {
+        fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            let message = format!("{} {}", record.level(), record.args());
+            MESSAGES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(message);
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Returns the messages recorded so far that mention `needle`.
+    pub fn messages(needle: &str) -> Vec<String> {
+        let messages = MESSAGES.lock().unwrap_or_else(PoisonError::into_inner);
+        messages
+            .iter()
+            .filter(|message| message.contains(needle))
+            .cloned()
+            .collect()
+    }
+
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&Recorder).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    #[test]
+    fn test_load_events() {
+        install();
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["INSTRUMENT_NEW", "INSTRUMENT_SET"]);
+        env::set_var("INSTRUMENT_SET", "kept");
+
+        Iter::new(
+            "INSTRUMENT_NEW=hunter2\nINSTRUMENT_SET=hunter3\nINSTRUMENT_NEW=again".as_bytes(),
+        )
+        .load()
+        .unwrap();
+
+        assert_eq!(
+            messages("INSTRUMENT_"),
+            [
+                "DEBUG loaded INSTRUMENT_NEW=<redacted>",
+                "DEBUG skipped INSTRUMENT_SET=<redacted>, which is already set",
+                "WARN INSTRUMENT_NEW is defined again on line 3, first on line 1; the later definition is ignored",
+            ]
+        );
+        assert!(messages("hunter").is_empty());
+    }
+
+    #[test]
+    fn test_find_events() {
+        install();
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("instrument.env"), "").unwrap();
+        env::set_current_dir(&dir).unwrap();
+
+        let found = Finder::new().filename(Path::new("instrument.env")).find();
+        let missing = Finder::new()
+            .filename(Path::new("instrument-missing.env"))
+            .find();
+
+        let found = found.unwrap().0;
+        assert!(messages("instrument.env").contains(&format!("DEBUG found '{}'", found.display())));
+        assert!(messages("instrument.env")
+            .contains(&format!("TRACE probed '{}' (found)", found.display())));
+        assert!(missing.is_err());
+        assert!(messages("instrument-missing.env")
+            .iter()
+            .any(|message| message.starts_with("DEBUG File not found, probed")));
+    }
+}
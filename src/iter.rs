@@ -1,32 +1,511 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::{BufReader, Lines};
+use std::io::{self, BufReader, Lines};
+use std::path::{Path, PathBuf};
 
-use crate::errors::{Error, Result};
-use crate::parse;
+use crate::errors::{Error, ParseErrorKind, Result};
+use crate::instrument::event;
+use crate::parse::{self, Dialect, Lookup};
+use crate::scoped::EnvGuard;
+#[cfg(feature = "strict")]
+use crate::strict::{self, UnsafeReason};
 
+/// What happens when a key is defined more than once in the same file.
+///
+/// The policy applies both to the pairs an `Iter` yields and to the value that later
+/// substitutions see, so `load` and substitution always agree on the value of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// The first definition is used and later ones are skipped.
+    #[default]
+    FirstWins,
+    /// Every definition is yielded, and the last one is used.
+    LastWins,
+    /// A second definition fails with `Error::DuplicateKey`, or with
+    /// `Error::DuplicateIncludedKey` if the two definitions are in different included files.
+    Error,
+}
+
+/// How deeply `# @include` directives may nest by default.
+const DEFAULT_MAX_INCLUDE_DEPTH: usize = 8;
+
+type KeyFilter = Box<dyn Fn(&str) -> bool + Send + Sync>;
+type KeyRename = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// An iterator over the variables of a .env file, read line by line from `R`.
+///
+/// Items are `(key, value)` pairs with substitutions already applied. Nothing is written to the
+/// environment until `load` or one of its variants is called.
 pub struct Iter<R> {
     lines: Lines<BufReader<R>>,
     substitution_data: HashMap<String, Option<String>>,
+    // the variables of the `Env` being loaded into, which take precedence over the process
+    // environment in substitutions
+    environment: HashMap<String, Option<String>>,
+    duplicates: DuplicatePolicy,
+    dialect: Dialect,
+    defined: HashMap<String, Definition>,
+    line: usize,
+    path: Option<PathBuf>,
+    // `path`, canonicalized once it is set
+    root: Option<PathBuf>,
+    // files read with non-blocking IO fail at an `# @include` rather than blocking on it
+    reject_includes: bool,
+    // files pulled in by `# @include`, innermost last
+    includes: Vec<Include>,
+    max_include_depth: usize,
+    #[cfg(feature = "strict")]
+    strict: bool,
+    unset_directives: bool,
+    // every filter must accept a key for it to be yielded
+    filters: Vec<KeyFilter>,
+    // applied in order to the keys that pass the filters
+    renames: Vec<KeyRename>,
+}
+
+struct Include {
+    path: PathBuf,
+    lines: Lines<BufReader<File>>,
+    line: usize,
+}
+
+/// The variables defined so far, over the variables of the `Env` being loaded into.
+struct Substitutions<'a> {
+    defined: &'a HashMap<String, Option<String>>,
+    environment: &'a HashMap<String, Option<String>>,
+}
+
+impl Lookup for Substitutions<'_> {
+    fn lookup(&self, name: &str) -> Option<&str> {
+        self.defined.lookup(name)
+    }
+
+    fn is_unset(&self, name: &str) -> bool {
+        self.defined.is_unset(name)
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        self.environment
+            .get(name)
+            .map_or_else(|| env::var(name).ok(), Clone::clone)
+    }
+}
+
+/// Where a key in use was defined.
+struct Definition {
+    // within `file`
+    line: usize,
+    // how deeply `file` is included
+    depth: usize,
+    // the included file, or `None` for the file being read
+    file: Option<PathBuf>,
 }
 
 impl<R: Read> Iter<R> {
+    /// Creates an iterator over the variables read from `reader`.
     pub fn new(reader: R) -> Self {
         Self {
             lines: BufReader::new(reader).lines(),
             substitution_data: HashMap::new(),
+            environment: HashMap::new(),
+            duplicates: DuplicatePolicy::default(),
+            dialect: Dialect::default(),
+            defined: HashMap::new(),
+            line: 0,
+            path: None,
+            root: None,
+            reject_includes: false,
+            includes: Vec::new(),
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+            #[cfg(feature = "strict")]
+            strict: false,
+            unset_directives: false,
+            filters: Vec::new(),
+            renames: Vec::new(),
         }
     }
 
-    pub fn load(self) -> Result<()> {
-        for item in self {
-            let (key, value) = item?;
-            if env::var(&key).is_err() {
+    /// Sets the path of the file being read.
+    ///
+    /// This enables `# @include <path>` directives, which are resolved relative to the file
+    /// containing them. The variables of the included file are read in place of the directive,
+    /// but a file's own entries take precedence over those of the files it includes, so it can
+    /// pull in shared defaults and override some of them. Without a path, such directives are
+    /// ordinary comments.
+    ///
+    /// An included value is still yielded before the entry that overrides it, which replaces
+    /// it when loading, like a later definition under `DuplicatePolicy::LastWins`.
+    ///
+    /// The functions that read files by path or name set this automatically.
+    #[must_use]
+    pub fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        self.root = fs::canonicalize(&path).ok();
+        self.path = Some(path);
+        self
+    }
+
+    /// Makes `# @include` directives fail with `Error::AsyncInclude`, for files that were
+    /// read with non-blocking IO.
+    #[cfg(feature = "async")]
+    pub(crate) const fn reject_includes(mut self) -> Self {
+        self.reject_includes = true;
+        self
+    }
+
+    /// Makes substitutions see the variables of the `Env` they are loaded into before the
+    /// process environment, where keys mapped to `None` are not set.
+    pub(crate) fn environment(mut self, environment: HashMap<String, Option<String>>) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Sets how deeply `# @include` directives may nest before `Error::IncludeTooDeep` is
+    /// returned. The default is 8.
+    #[must_use]
+    pub const fn max_include_depth(mut self, depth: usize) -> Self {
+        self.max_include_depth = depth;
+        self
+    }
+
+    /// Refuses `# @include` targets that are unsafe to load secrets from, as described in
+    /// `open_checked`, with `Error::UnsafeFile`. Targets must be inside the directory of the
+    /// file set by `with_path`. Off by default, and set by `Finder::strict`.
+    #[cfg(feature = "strict")]
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets what happens to keys that are defined more than once.
+    #[must_use]
+    pub const fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Enables `unset KEY` lines, which remove `KEY` from the environment when loading, even
+    /// if it was set before, and make later substitutions of it empty. They are off by
+    /// default, so that such a line is a parse error.
+    ///
+    /// Unset keys are not yielded as pairs, see `changes` to tell them apart from empty values.
+    /// A key defined again after it was unset counts as a new definition.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv as dotenv;
+    ///
+    /// let changes = dotenv::from_str_iter("EMPTY=\nunset REMOVED")
+    ///     .unset_directives(true)
+    ///     .changes()
+    ///     .collect::<dotenv::Result<Vec<_>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(changes[0], ("EMPTY".to_owned(), Some(String::new())));
+    /// assert_eq!(changes[1], ("REMOVED".to_owned(), None));
+    /// ```
+    #[must_use]
+    pub const fn unset_directives(mut self, enabled: bool) -> Self {
+        self.unset_directives = enabled;
+        self
+    }
+
+    /// Sets the syntax that lines are parsed with.
+    #[must_use]
+    pub const fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Only yields keys for which `predicate` returns true.
+    ///
+    /// Filters see the keys as written in the file, before any `rename` or `strip_prefix`, and
+    /// every filter must accept a key for it to be yielded. Filtered out keys can still be
+    /// substituted into the values of other keys.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv as dotenv;
+    ///
+    /// let pairs = dotenv::from_str_iter("PORT=80\nDEBUG=1")
+    ///     .filter_keys(|key| key != "DEBUG")
+    ///     .collect::<dotenv::Result<Vec<_>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pairs, [("PORT".to_string(), "80".to_string())]);
+    /// ```
+    #[must_use]
+    pub fn filter_keys<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    /// Only yields keys that start with `prefix`.
+    #[must_use]
+    pub fn filter_prefix<S: Into<String>>(self, prefix: S) -> Self {
+        let prefix = prefix.into();
+        self.filter_keys(move |key| key.starts_with(&prefix))
+    }
+
+    /// Only yields keys that match `pattern`, where `*` matches any number of characters and
+    /// `?` matches exactly one.
+    #[must_use]
+    pub fn filter_glob<S: Into<String>>(self, pattern: S) -> Self {
+        let pattern = pattern.into();
+        self.filter_keys(move |key| glob_match(&pattern, key))
+    }
+
+    /// Yields keys renamed by `rename`, so that they are set under the new name.
+    ///
+    /// Renames are applied in the order they were added, after the filters. Substitutions
+    /// keep referring to the keys as written in the file.
+    #[must_use]
+    pub fn rename<F>(mut self, rename: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.renames.push(Box::new(rename));
+        self
+    }
+
+    /// Removes `prefix` from the keys that start with it.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv as dotenv;
+    ///
+    /// let pairs = dotenv::from_str_iter("BILLING_URL=https://billing\nAUTH_URL=https://auth")
+    ///     .filter_prefix("BILLING_")
+    ///     .strip_prefix("BILLING_")
+    ///     .collect::<dotenv::Result<Vec<_>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pairs, [("URL".to_string(), "https://billing".to_string())]);
+    /// ```
+    #[must_use]
+    pub fn strip_prefix<S: Into<String>>(self, prefix: S) -> Self {
+        let prefix = prefix.into();
+        self.rename(move |key| key.strip_prefix(prefix.as_str()).unwrap_or(key).to_string())
+    }
+
+    /// Yields every change the file makes, where `None` is a key removed by an `unset`
+    /// directive, as opposed to `Some(String::new())` for a key defined with an empty value.
+    #[must_use]
+    pub const fn changes(self) -> Changes<R> {
+        Changes { iter: self }
+    }
+
+    load_methods!(self.changes());
+}
+
+/// An iterator over the changes a .env file makes, created by `Iter::changes`.
+///
+/// Items are `(key, value)` pairs where the value is `None` for a key removed by an `unset`
+/// directive.
+pub struct Changes<R> {
+    iter: Iter<R>,
+}
+
+impl<R: Read> Iterator for Changes<R> {
+    type Item = Result<(String, Option<String>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_change()
+    }
+}
+
+/// Defines `load`, `load_override` and `load_scoped`, which load the pairs that `self`, or the
+/// given method of `self`, yields with `load_pairs`.
+macro_rules! load_methods {
+    ($self:ident $(.$pairs:ident())?) => {
+        $crate::iter::load_methods!(@define [$self] $self $($pairs)?);
+    };
+    (&$self:ident $(.$pairs:ident())?) => {
+        $crate::iter::load_methods!(@define [&$self] $self $($pairs)?);
+    };
+    (@define [$($receiver:tt)+] $self:ident $($pairs:ident)?) => {
+        /// Sets every variable in the environment, keeping variables that are already set.
+        pub fn load($($receiver)+) -> $crate::errors::Result<()> {
+            $crate::iter::load_pairs($self$(.$pairs())?, false, None)
+        }
+
+        /// Like `load`, but overwrites variables that are already set.
+        pub fn load_override($($receiver)+) -> $crate::errors::Result<()> {
+            $crate::iter::load_pairs($self$(.$pairs())?, true, None)
+        }
+
+        /// Like `load`, but returns a guard that removes the variables set here once dropped.
+        ///
+        /// If an item fails to parse, the variables loaded so far are removed before the error
+        /// is returned.
+        pub fn load_scoped($($receiver)+) -> $crate::errors::Result<$crate::scoped::EnvGuard> {
+            let mut guard = $crate::scoped::EnvGuard::default();
+            $crate::iter::load_pairs($self$(.$pairs())?, false, Some(&mut guard))?;
+            Ok(guard)
+        }
+    };
+}
+
+pub(crate) use load_methods;
+
+/// Sets every pair in the environment, skipping variables that were already set before the
+/// load started unless `override_existing` is true. Later pairs replace earlier ones, so that
+/// `DuplicatePolicy::LastWins` behaves the same for loading as for substitution. A `None`
+/// value removes the variable, whether or not it was set before.
+pub fn load_pairs<I, V>(
+    pairs: I,
+    override_existing: bool,
+    mut guard: Option<&mut EnvGuard>,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Result<(String, V)>>,
+    V: Into<Option<String>>,
+{
+    let mut loaded = HashSet::new();
+    for item in pairs {
+        let (key, value) = item?;
+        match value.into() {
+            None => {
+                if let Some(guard) = guard.as_deref_mut() {
+                    guard.record(key.clone().into());
+                }
+                event!(debug, "unset {key}");
+                env::remove_var(&key);
+            }
+            Some(value)
+                if override_existing || loaded.contains(&key) || env::var(&key).is_err() =>
+            {
+                if let Some(guard) = guard.as_deref_mut() {
+                    guard.record(key.clone().into());
+                }
+                event!(debug, "loaded {key}=<redacted>");
                 env::set_var(&key, value);
+                loaded.insert(key);
+            }
+            Some(_) => event!(debug, "skipped {key}=<redacted>, which is already set"),
+        }
+    }
+
+    Ok(())
+}
+
+impl<R: Read> Iter<R> {
+    /// Reads the next line, from the innermost included file that has lines left.
+    fn next_line(&mut self) -> Option<(io::Result<String>, usize)> {
+        loop {
+            if let Some(line) = self.next_line_in_file() {
+                return Some(line);
+            }
+            // once the root file has no lines left either, there is nothing to pop
+            self.includes.pop()?;
+        }
+    }
+
+    /// Reads the next line of the innermost file, without moving on to the file including it.
+    fn next_line_in_file(&mut self) -> Option<(io::Result<String>, usize)> {
+        if let Some(include) = self.includes.last_mut() {
+            let line = include.lines.next()?;
+            include.line += 1;
+            Some((line, include.line))
+        } else {
+            let line = self.lines.next()?;
+            self.line += 1;
+            Some((line, self.line))
+        }
+    }
+
+    /// Parses `line`, joining the lines that follow it while the dialect allows a quoted value
+    /// to continue on them.
+    ///
+    /// The value can only continue within the same file, and the joined lines are only parsed
+    /// again once one of them has the closing quote.
+    fn parse(&mut self, mut line: String) -> parse::ParsedLine {
+        loop {
+            let substitutions = Substitutions {
+                defined: &self.substitution_data,
+                environment: &self.environment,
+            };
+            let err = match parse::parse_line(&line, &substitutions, self.dialect) {
+                Err(Error::LineParse(err))
+                    if err.kind() == ParseErrorKind::UnterminatedQuote
+                        && self.dialect.multiline() =>
+                {
+                    err
+                }
+                result => return result,
+            };
+            let quote = match line
+                .get(err.span().start..)
+                .and_then(|rest| rest.chars().next())
+            {
+                Some(quote) => quote,
+                None => return Err(Error::LineParse(err)),
+            };
+
+            loop {
+                match self.next_line_in_file() {
+                    Some((Ok(next), _)) => {
+                        line.push('\n');
+                        line.push_str(&next);
+                        if parse::closing_quote(&next, quote).is_some() {
+                            break;
+                        }
+                    }
+                    Some((Err(err), _)) => return Err(Error::Io(err)),
+                    None => return Err(Error::LineParse(err)),
+                }
             }
         }
+    }
+
+    /// Starts reading `target`, resolved relative to the file containing the directive.
+    fn include(&mut self, target: &str) -> Result<()> {
+        let including = self
+            .includes
+            .last()
+            .map(|include| include.path.as_path())
+            .or(self.path.as_deref())
+            .unwrap_or_else(|| Path::new(""));
+        let path = including
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(target);
+        let path = fs::canonicalize(&path).map_err(Error::Io)?;
+
+        if self.root.as_ref() == Some(&path)
+            || self.includes.iter().any(|include| include.path == path)
+        {
+            return Err(Error::IncludeCycle(path));
+        }
+        if self.includes.len() >= self.max_include_depth {
+            return Err(Error::IncludeTooDeep(path));
+        }
 
+        #[cfg(feature = "strict")]
+        let file = if self.strict {
+            // without a root file, no directory is trusted
+            let directory = self.root.as_deref().and_then(Path::parent).ok_or_else(|| {
+                Error::UnsafeFile(path.clone(), UnsafeReason::OutsideDirectory(path.clone()))
+            })?;
+            strict::open_checked(&path, directory)?
+        } else {
+            File::open(&path).map_err(Error::Io)?
+        };
+        #[cfg(not(feature = "strict"))]
+        let file = File::open(&path).map_err(Error::Io)?;
+        event!(debug, "including '{}'", path.display());
+        self.includes.push(Include {
+            path,
+            lines: BufReader::new(file).lines(),
+            line: 0,
+        });
         Ok(())
     }
 }
@@ -36,17 +515,332 @@ impl<R: Read> Iterator for Iter<R> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let line = match self.lines.next() {
-                Some(Ok(line)) => line,
-                Some(Err(err)) => return Some(Err(Error::Io(err))),
-                None => return None,
+            match self.next_change()? {
+                Ok((key, Some(value))) => return Some(Ok((key, value))),
+                Ok((_, None)) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iter<R> {
+    fn next_change(&mut self) -> Option<Result<(String, Option<String>)>> {
+        loop {
+            let (line, line_number) = match self.next_line()? {
+                (Ok(line), line_number) => (line, line_number),
+                (Err(err), _) => return Some(Err(Error::Io(err))),
             };
 
-            match parse::parse_line(&line, &mut self.substitution_data) {
-                Ok(Some(result)) => return Some(Ok(result)),
-                Ok(None) => {}
+            if self.reject_includes {
+                if let Some(target) = parse::parse_include(&line) {
+                    return Some(Err(Error::AsyncInclude(PathBuf::from(target))));
+                }
+            }
+            if self.path.is_some() {
+                if let Some(target) = parse::parse_include(&line) {
+                    match self.include(target) {
+                        Ok(()) => continue,
+                        Err(err) => return Some(Err(err)),
+                    }
+                }
+            }
+            let depth = self.includes.len();
+
+            if self.unset_directives {
+                if let Some(key) = parse::parse_unset(&line) {
+                    let key = key.to_owned();
+                    self.defined.remove(&key);
+                    self.substitution_data.insert(key.clone(), None);
+                    match self.output_key(key) {
+                        Some(key) => return Some(Ok((key, None))),
+                        None => continue,
+                    }
+                }
+            }
+
+            let entry = match self.parse(line) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
                 Err(err) => return Some(Err(err)),
+            };
+
+            if let Some(first) = self.defined.get(&entry.key) {
+                let first_line = first.line;
+                // a file's own entries take precedence over those of the files it includes,
+                // and the policy only applies to keys defined twice at the same depth
+                match (depth.cmp(&first.depth), self.duplicates) {
+                    (Ordering::Greater, _) => {
+                        event!(
+                            debug,
+                            "{} on line {line_number} is ignored, as an including file defines \
+                             it",
+                            entry.key
+                        );
+                        continue;
+                    }
+                    (Ordering::Less, _) => {
+                        event!(
+                            debug,
+                            "{} on line {line_number} overrides the value of an included file",
+                            entry.key
+                        );
+                        self.define(entry.key.clone(), line_number);
+                    }
+                    (Ordering::Equal, DuplicatePolicy::FirstWins) => {
+                        event!(
+                            warn,
+                            "{} is defined again on line {line_number}, first on line \
+                             {first_line}; the later definition is ignored",
+                            entry.key
+                        );
+                        continue;
+                    }
+                    (Ordering::Equal, DuplicatePolicy::LastWins) => {
+                        event!(
+                            warn,
+                            "{} is defined again on line {line_number}, first on line \
+                             {first_line}; the later definition is used",
+                            entry.key
+                        );
+                    }
+                    (Ordering::Equal, DuplicatePolicy::Error) => {
+                        let file = self.includes.last().map(|include| &include.path);
+                        let err = match (&first.file, file) {
+                            (Some(first_file), Some(file)) if first_file != file => {
+                                Error::DuplicateIncludedKey(
+                                    entry.key,
+                                    first_file.clone(),
+                                    first_line,
+                                    file.clone(),
+                                    line_number,
+                                )
+                            }
+                            _ => Error::DuplicateKey(entry.key, first_line, line_number),
+                        };
+                        return Some(Err(err));
+                    }
+                }
+            } else {
+                self.define(entry.key.clone(), line_number);
+            }
+
+            self.substitution_data
+                .insert(entry.key.clone(), Some(entry.value.clone()));
+
+            if let Some(key) = self.output_key(entry.key) {
+                return Some(Ok((key, Some(entry.value))));
             }
         }
     }
+
+    /// Records that `key` is defined on `line` of the innermost file.
+    fn define(&mut self, key: String, line: usize) {
+        let definition = Definition {
+            line,
+            depth: self.includes.len(),
+            file: self.includes.last().map(|include| include.path.clone()),
+        };
+        self.defined.insert(key, definition);
+    }
+
+    /// Applies the renames to `key`, or returns `None` if a filter rejects it.
+    fn output_key(&self, key: String) -> Option<String> {
+        if !self.filters.iter().all(|filter| filter(&key)) {
+            return None;
+        }
+        Some(self.renames.iter().fold(key, |key, rename| rename(&key)))
+    }
+}
+
+/// Matches `text` against a pattern where `*` matches any run of characters and `?` matches
+/// a single one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in the pattern, and of the text it was tried against
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lock_env;
+
+    const DUPLICATED: &str = "DUP=first\nFROM_DUP=$DUP\nDUP=second\nAFTER=$DUP\n";
+
+    fn collect(policy: DuplicatePolicy) -> Result<Vec<(String, String)>> {
+        Iter::new(DUPLICATED.as_bytes())
+            .duplicates(policy)
+            .collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_first_wins() {
+        assert_eq!(
+            collect(DuplicatePolicy::FirstWins).unwrap(),
+            pairs(&[("DUP", "first"), ("FROM_DUP", "first"), ("AFTER", "first")])
+        );
+    }
+
+    #[test]
+    fn test_last_wins() {
+        assert_eq!(
+            collect(DuplicatePolicy::LastWins).unwrap(),
+            pairs(&[
+                ("DUP", "first"),
+                ("FROM_DUP", "first"),
+                ("DUP", "second"),
+                ("AFTER", "second")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_duplicate_error_reports_both_lines() {
+        let err = collect(DuplicatePolicy::Error).unwrap_err();
+
+        assert!(matches!(err, Error::DuplicateKey(key, 1, 3) if key == "DUP"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("BILLING_*", "BILLING_URL"));
+        assert!(glob_match("*_URL", "BILLING_URL"));
+        assert!(glob_match("*LL*_?RL", "BILLING_URL"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("BILLING_?", "BILLING_URL"));
+        assert!(!glob_match("*_PORT", "BILLING_URL"));
+    }
+
+    #[test]
+    fn test_filters_see_original_keys() {
+        let input = "APP_HOST=localhost\nAPP_URL=http://${APP_HOST}\nOTHER_HOST=remote\n";
+
+        let filtered: Vec<_> = Iter::new(input.as_bytes())
+            .filter_glob("APP_*")
+            .filter_keys(|key| key != "APP_HOST")
+            .strip_prefix("APP_")
+            .rename(str::to_lowercase)
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(filtered, pairs(&[("url", "http://localhost")]));
+    }
+
+    #[test]
+    fn test_load_applies_filters() {
+        let _lock = lock_env();
+        let _existing = EnvGuard::capture(["ITER_OTHER_KEY"]);
+
+        let _guard = Iter::new(b"ITER_PREFIXED_KEY=1\nITER_OTHER_KEY=2".as_ref())
+            .filter_prefix("ITER_PREFIXED_")
+            .strip_prefix("ITER_PREFIXED_")
+            .rename(|key| format!("ITER_RENAMED_{key}"))
+            .load_scoped()
+            .unwrap();
+
+        assert_eq!(env::var("ITER_RENAMED_KEY").unwrap(), "1");
+        assert!(env::var("ITER_OTHER_KEY").is_err());
+    }
+
+    #[test]
+    fn test_load_applies_policy() {
+        let _lock = lock_env();
+        let input = "ITER_DUPLICATE=first\nITER_DUPLICATE=second";
+
+        let guard = Iter::new(input.as_bytes()).load_scoped().unwrap();
+        assert_eq!(env::var("ITER_DUPLICATE").unwrap(), "first");
+        drop(guard);
+
+        let _guard = Iter::new(input.as_bytes())
+            .duplicates(DuplicatePolicy::LastWins)
+            .load_scoped()
+            .unwrap();
+        assert_eq!(env::var("ITER_DUPLICATE").unwrap(), "second");
+    }
+
+    #[test]
+    fn test_unset_directives() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["ITER_INHERITED"]);
+        env::set_var("ITER_INHERITED", "from shell");
+        let input = "ITER_EMPTY=\nunset ITER_INHERITED # not wanted here\nITER_REF=<${ITER_INHERITED}>\nITER_AGAIN=1\nunset ITER_AGAIN\nITER_AGAIN=2";
+
+        let changes: Vec<_> = Iter::new(input.as_bytes())
+            .unset_directives(true)
+            .changes()
+            .collect::<Result<_>>()
+            .unwrap();
+        let some = |value: &str| Some(value.to_owned());
+        assert_eq!(
+            changes,
+            [
+                ("ITER_EMPTY".to_owned(), some("")),
+                ("ITER_INHERITED".to_owned(), None),
+                ("ITER_REF".to_owned(), some("<>")),
+                ("ITER_AGAIN".to_owned(), some("1")),
+                ("ITER_AGAIN".to_owned(), None),
+                ("ITER_AGAIN".to_owned(), some("2")),
+            ]
+        );
+
+        let guard = Iter::new(input.as_bytes())
+            .unset_directives(true)
+            .load_scoped()
+            .unwrap();
+        assert!(env::var("ITER_INHERITED").is_err());
+        assert_eq!(env::var("ITER_EMPTY").unwrap(), "");
+        assert_eq!(env::var("ITER_AGAIN").unwrap(), "2");
+        drop(guard);
+        assert_eq!(env::var("ITER_INHERITED").unwrap(), "from shell");
+
+        // without the flag, the directive does not parse
+        let err = Iter::new(input.as_bytes()).load().unwrap_err();
+        assert!(matches!(err, Error::LineParse(_)));
+    }
+
+    #[test]
+    fn test_parse_unset() {
+        assert_eq!(parse::parse_unset("unset KEY"), Some("KEY"));
+        assert_eq!(
+            parse::parse_unset("  unset\tA.B_1  # comment"),
+            Some("A.B_1")
+        );
+        assert_eq!(parse::parse_unset("unset=1"), None);
+        assert_eq!(parse::parse_unset("unset A B"), None);
+        assert_eq!(parse::parse_unset("unset 1A"), None);
+        assert_eq!(parse::parse_unset("unset"), None);
+    }
 }
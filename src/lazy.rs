@@ -0,0 +1,328 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Once, PoisonError};
+
+use crate::errors::{Error, Result};
+use crate::find::Finder;
+use crate::instrument::event;
+
+static START: Once = Once::new();
+static STATE: Mutex<State> = Mutex::new(State {
+    loader: None,
+    started: false,
+    status: None,
+});
+
+struct State {
+    loader: Option<LazyLoader>,
+    started: bool,
+    status: Option<std::result::Result<PathBuf, Arc<Error>>>,
+}
+
+impl State {
+    fn install(&mut self, loader: LazyLoader) -> bool {
+        if self.started {
+            return false;
+        }
+        self.loader = Some(loader);
+        true
+    }
+
+    /// Runs the installed loader, returning the message to panic with if it failed under
+    /// `ErrorPolicy::Panic`.
+    fn start(&mut self) -> Option<String> {
+        self.started = true;
+        let loader = self.loader.take().unwrap_or_default();
+        let status = loader.run();
+        let panic = match (&status, loader.error_policy) {
+            (Err(err), ErrorPolicy::Panic) => Some(format!(
+                "dotenv: could not load {}: {err}",
+                loader.filename.display()
+            )),
+            _ => None,
+        };
+        self.status = Some(status);
+        panic
+    }
+
+    fn status(&self) -> LoadStatus {
+        match &self.status {
+            None => LoadStatus::Pending,
+            Some(Ok(path)) => LoadStatus::Loaded(path.clone()),
+            Some(Err(err)) => LoadStatus::Failed(Arc::clone(err)),
+        }
+    }
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// What the implicit load should do when the file cannot be found or parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Carry on as if the file was empty. The error is still available from `load_status`,
+    /// and logged at debug level with the `log` or `tracing` feature.
+    #[default]
+    Ignore,
+    /// Like `Ignore`, but log the error at warn level, or print it to stderr if neither the
+    /// `log` nor the `tracing` feature is enabled.
+    Warn,
+    /// Panic with the error.
+    Panic,
+}
+
+/// Configures the load that `var` and `vars` perform the first time either is called.
+///
+/// By default, `.env` is searched for in the current directory and its parents, variables
+/// that are already set are kept, and errors are ignored.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv::{ErrorPolicy, LazyLoader};
+///
+/// let installed = LazyLoader::new()
+///     .filename(".env.local")
+///     .error_policy(ErrorPolicy::Warn)
+///     .install();
+/// assert!(installed);
+///
+/// let _ = sd_dotenv::var("DATABASE_URL");
+/// ```
+#[derive(Debug, Clone)]
+pub struct LazyLoader {
+    filename: PathBuf,
+    override_existing: bool,
+    error_policy: ErrorPolicy,
+    #[cfg(feature = "strict")]
+    strict: bool,
+}
+
+impl Default for LazyLoader {
+    fn default() -> Self {
+        Self {
+            filename: PathBuf::from(".env"),
+            override_existing: false,
+            error_policy: ErrorPolicy::default(),
+            #[cfg(feature = "strict")]
+            strict: false,
+        }
+    }
+}
+
+impl LazyLoader {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the file to search for.
+    #[must_use]
+    pub fn filename<P: AsRef<Path>>(mut self, filename: P) -> Self {
+        self.filename = filename.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets whether variables from the file replace variables that are already set.
+    #[must_use]
+    pub const fn override_existing(mut self, override_existing: bool) -> Self {
+        self.override_existing = override_existing;
+        self
+    }
+
+    #[must_use]
+    pub const fn error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Refuses to load a file that is unsafe to load secrets from, as described in
+    /// `open_checked`.
+    #[cfg(feature = "strict")]
+    #[must_use]
+    pub const fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Makes this the configuration used by `var` and `vars`.
+    ///
+    /// Returns `false`, leaving the configuration untouched, if the implicit load has already
+    /// happened.
+    #[must_use = "the configuration is ignored if the implicit load already happened"]
+    pub fn install(self) -> bool {
+        state().install(self)
+    }
+
+    fn load(&self) -> Result<PathBuf> {
+        let finder = Finder::new().filename(&self.filename);
+        #[cfg(feature = "strict")]
+        let finder = finder.strict(self.strict);
+        let (path, iter) = finder.find()?;
+        if self.override_existing {
+            iter.load_override()?;
+        } else {
+            iter.load()?;
+        }
+        Ok(path)
+    }
+
+    fn run(&self) -> std::result::Result<PathBuf, Arc<Error>> {
+        self.load().map_err(|err| {
+            match self.error_policy {
+                ErrorPolicy::Ignore => {
+                    event!(debug, "could not load {}: {err}", self.filename.display());
+                }
+                ErrorPolicy::Warn => {
+                    event!(warn, "could not load {}: {err}", self.filename.display());
+                    #[cfg(not(any(feature = "log", feature = "tracing")))]
+                    eprintln!("dotenv: could not load {}: {err}", self.filename.display());
+                }
+                // the panic reports the error
+                ErrorPolicy::Panic => {}
+            }
+            Arc::new(err)
+        })
+    }
+}
+
+/// The outcome of the implicit load performed by `var` and `vars`.
+#[derive(Debug, Clone)]
+pub enum LoadStatus {
+    /// Neither `var` nor `vars` has been called yet.
+    Pending,
+    /// The file at this path was loaded.
+    Loaded(PathBuf),
+    /// The load failed with this error, which was handled according to the `ErrorPolicy`.
+    Failed(Arc<Error>),
+}
+
+/// Returns the outcome of the implicit load performed by `var` and `vars`.
+#[must_use]
+pub fn load_status() -> LoadStatus {
+    state().status()
+}
+
+/// Performs the implicit load, unless it already happened.
+pub fn ensure_loaded() {
+    ensure(&START, &STATE);
+}
+
+fn ensure(once: &Once, state: &Mutex<State>) {
+    let mut panic = None;
+    once.call_once(|| {
+        panic = state.lock().unwrap_or_else(PoisonError::into_inner).start();
+    });
+    // panicking within `call_once` would poison it for every later `var` and `vars`
+    if let Some(message) = panic {
+        panic!("{message}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{lock_env, EnvGuard};
+
+    #[test]
+    fn test_install_after_load_is_rejected() {
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(&dir).unwrap();
+        let mut state = State {
+            loader: None,
+            started: false,
+            status: None,
+        };
+
+        assert!(state.install(LazyLoader::new().filename("missing.env")));
+        assert!(matches!(state.status(), LoadStatus::Pending));
+
+        state.start();
+        assert!(!state.install(LazyLoader::new()));
+        assert!(matches!(state.status(), LoadStatus::Failed(err) if err.not_found()));
+    }
+
+    #[test]
+    fn test_run_honours_filename_and_override() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["LAZY_KEY"]);
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(&dir).unwrap();
+        fs::write(dir.path().join("lazy.env"), "LAZY_KEY=from_file").unwrap();
+        env::set_var("LAZY_KEY", "from_env");
+
+        let loader = LazyLoader::new().filename("lazy.env");
+        assert!(loader.run().is_ok());
+        assert_eq!(env::var("LAZY_KEY").unwrap(), "from_env");
+
+        let loader = loader.override_existing(true);
+        assert_eq!(loader.run().unwrap(), dir.path().join("lazy.env"));
+        assert_eq!(env::var("LAZY_KEY").unwrap(), "from_file");
+    }
+
+    #[test]
+    fn test_run_reports_swallowed_error() {
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(&dir).unwrap();
+
+        let err = LazyLoader::new().filename("missing.env").run().unwrap_err();
+        assert!(err.not_found());
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn test_failure_is_logged_at_policy_level() {
+        use crate::instrument::test::{install, messages};
+
+        install();
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(&dir).unwrap();
+
+        let ignored = LazyLoader::new().filename("lazy-ignored.env").run();
+        let warned = LazyLoader::new()
+            .filename("lazy-warned.env")
+            .error_policy(ErrorPolicy::Warn)
+            .run();
+
+        assert!(ignored.is_err() && warned.is_err());
+        let ignored = messages("could not load lazy-ignored.env");
+        let warned = messages("could not load lazy-warned.env");
+        assert_eq!(ignored.len(), 1);
+        assert!(ignored[0].starts_with("DEBUG "));
+        assert_eq!(warned.len(), 1);
+        assert!(warned[0].starts_with("WARN "));
+    }
+
+    #[test]
+    fn test_panic_policy_panics_outside_once() {
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(&dir).unwrap();
+        let once = Once::new();
+        let state = Mutex::new(State {
+            loader: Some(
+                LazyLoader::new()
+                    .filename("missing.env")
+                    .error_policy(ErrorPolicy::Panic),
+            ),
+            started: false,
+            status: None,
+        });
+
+        let panic = std::panic::catch_unwind(|| ensure(&once, &state)).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("dotenv: could not load missing.env"));
+
+        // later calls neither panic nor find the once poisoned
+        assert!(once.is_completed());
+        ensure(&once, &state);
+        let status = state.lock().unwrap().status();
+        assert!(matches!(status, LoadStatus::Failed(err) if err.not_found()));
+    }
+}
@@ -30,26 +30,63 @@
 #![forbid(unsafe_code, deprecated_in_future)]
 #![allow(clippy::missing_errors_doc, clippy::module_name_repetitions)]
 
+mod borrowed;
+mod diff;
+mod entry;
+mod env_file;
 mod errors;
+mod export;
 mod find;
+mod fmt;
+mod instrument;
 mod iter;
+mod lazy;
+mod lint;
 mod parse;
+mod scoped;
+mod store;
+#[cfg(feature = "strict")]
+mod strict;
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+mod structured;
 mod tests;
+#[cfg(feature = "watch")]
+mod watch;
 
 use std::env::{self, Vars};
 use std::ffi::OsStr;
 use std::fs::File;
+#[cfg(feature = "async")]
+use std::io::Cursor;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::Once;
 
+pub use crate::borrowed::StrIter;
+pub use crate::diff::Diff;
+pub use crate::entry::{Entry, EntryIter, Position, Quoting, Reference, Span};
+pub use crate::env_file::{EnvFile, EnvFileIter, Precedence};
 pub use crate::errors::*;
-use crate::find::Finder;
-use crate::iter::Iter;
-
-static START: Once = Once::new();
+pub use crate::export::{Exporter, Format, UnknownFormat};
+pub use crate::find::{Finder, Probe, ProbeOutcome};
+pub use crate::fmt::{fmt, Formatter};
+pub use crate::iter::{Changes, DuplicatePolicy, Iter};
+pub use crate::lazy::{load_status, ErrorPolicy, LazyLoader, LoadStatus};
+pub use crate::lint::{lint, Diagnostic, LintConfig, Rule, Severity, UnknownRule};
+pub use crate::parse::{Comments, Dialect, DialectRules, Escapes, Expansion, Quotes};
+pub use crate::scoped::{load_scoped, lock_env, EnvGuard, EnvLock};
+pub use crate::store::{Env, EnvIter};
+#[cfg(feature = "strict")]
+pub use crate::strict::{open_checked, UnsafeReason};
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+pub use crate::structured::{from_structured_path_iter, StructuredIter};
+#[cfg(feature = "watch")]
+pub use crate::watch::Watcher;
 
 /// After loading the dotenv file, fetches the environment variable key from the current process.
 ///
+/// The file is loaded the first time `var` or `vars` is called, as configured with `LazyLoader`.
+/// Any error is handled according to its `ErrorPolicy` and can be inspected with `load_status`.
+///
 /// The returned result is Ok(s) if the environment variable is present and is valid unicode. If the
 /// environment variable is not present, or it is not valid unicode, then Err will be returned.
 ///
@@ -57,15 +94,13 @@ static START: Once = Once::new();
 ///
 /// ```no_run
 ///
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 ///
 /// let key = "FOO";
 /// let value= dotenv::var(key).unwrap();
 /// ```
 pub fn var<K: AsRef<OsStr>>(key: K) -> Result<String> {
-    START.call_once(|| {
-        dotenv().ok();
-    });
+    lazy::ensure_loaded();
     env::var(key).map_err(Error::EnvVar)
 }
 
@@ -80,15 +115,14 @@ pub fn var<K: AsRef<OsStr>>(key: K) -> Result<String> {
 ///
 /// ```no_run
 ///
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// use std::io;
 ///
 /// let result: Vec<(String, String)> = dotenv::vars().collect();
 /// ```
+#[must_use]
 pub fn vars() -> Vars {
-    START.call_once(|| {
-        dotenv().ok();
-    });
+    lazy::ensure_loaded();
     env::vars()
 }
 
@@ -97,7 +131,7 @@ pub fn vars() -> Vars {
 /// Examples
 ///
 /// ```
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// use std::env;
 /// use std::path::{Path};
 ///
@@ -105,8 +139,7 @@ pub fn vars() -> Vars {
 /// dotenv::from_path(my_path.as_path());
 /// ```
 pub fn from_path<P: AsRef<Path>>(path: P) -> Result<()> {
-    let iter = Iter::new(File::open(path).map_err(Error::Io)?);
-    iter.load()
+    from_path_iter(path)?.load()
 }
 
 /// Like `from_path`, but returns an iterator over variables instead of loading into environment.
@@ -114,7 +147,7 @@ pub fn from_path<P: AsRef<Path>>(path: P) -> Result<()> {
 /// Examples
 ///
 /// ```no_run
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// use std::env;
 /// use std::path::{Path};
 ///
@@ -127,14 +160,87 @@ pub fn from_path<P: AsRef<Path>>(path: P) -> Result<()> {
 /// }
 /// ```
 pub fn from_path_iter<P: AsRef<Path>>(path: P) -> Result<Iter<File>> {
-    Ok(Iter::new(File::open(path).map_err(Error::Io)?))
+    let path = path.as_ref();
+    Ok(Iter::new(File::open(path).map_err(Error::Io)?).with_path(path))
+}
+
+/// Loads the variables of a JSON, YAML or TOML document, such as `config.local.json`.
+///
+/// The format is chosen from the extension of the file, and the document is flattened as
+/// described for `StructuredIter`. Variables that are already set are kept, like `from_path`
+/// does.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv as dotenv;
+///
+/// dotenv::from_structured_path("config.local.json").unwrap();
+/// // {"db": {"port": 5432}} sets db__port=5432
+/// ```
+#[cfg(any(feature = "json", feature = "yaml", feature = "toml"))]
+pub fn from_structured_path<P: AsRef<Path>>(path: P) -> Result<()> {
+    from_structured_path_iter(path)?.load()
+}
+
+/// Loads variables from any reader, such as a network stream or an in-memory buffer.
+///
+/// `# @include` directives are not followed, since there is no file to resolve them against.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+/// use std::io::Cursor;
+///
+/// let contents = Cursor::new(b"READER_KEY=from_reader".to_vec());
+/// dotenv::from_reader(contents).unwrap();
+/// assert_eq!(std::env::var("READER_KEY").unwrap(), "from_reader");
+/// ```
+pub fn from_reader<R: Read>(reader: R) -> Result<()> {
+    from_read_iter(reader).load()
+}
+
+/// Like `from_reader`, but returns an iterator over variables instead of loading into
+/// environment.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+///
+/// let contents = "API_TOKEN=hunter2\nAPI_URL=https://example.com";
+/// let pairs = dotenv::from_read_iter(contents.as_bytes())
+///     .collect::<dotenv::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert_eq!(pairs[0], ("API_TOKEN".to_string(), "hunter2".to_string()));
+/// ```
+pub fn from_read_iter<R: Read>(reader: R) -> Iter<R> {
+    Iter::new(reader)
+}
+
+/// Loads variables from a string, such as a file embedded with `include_str!`.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+///
+/// dotenv::from_str("EMBEDDED_KEY=embedded").unwrap();
+/// assert_eq!(std::env::var("EMBEDDED_KEY").unwrap(), "embedded");
+/// ```
+pub fn from_str(contents: &str) -> Result<()> {
+    from_reader(contents.as_bytes())
+}
+
+/// Like `from_str`, but returns an iterator over variables instead of loading into environment.
+#[must_use]
+pub fn from_str_iter(contents: &str) -> Iter<&[u8]> {
+    from_read_iter(contents.as_bytes())
 }
 
 /// Loads the specified file from the environment's current directory or its parents in sequence.
 ///
 /// # Examples
 /// ```
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// dotenv::from_filename("custom.env").ok();
 /// ```
 ///
@@ -142,7 +248,7 @@ pub fn from_path_iter<P: AsRef<Path>>(path: P) -> Result<Iter<File>> {
 /// which is preferred.
 ///
 /// ```
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// dotenv::from_filename(".env").ok();
 /// ```
 pub fn from_filename<P: AsRef<Path>>(filename: P) -> Result<PathBuf> {
@@ -155,7 +261,7 @@ pub fn from_filename<P: AsRef<Path>>(filename: P) -> Result<PathBuf> {
 ///
 /// # Examples
 /// ```
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// dotenv::from_filename("custom.env").ok();
 /// ```
 ///
@@ -163,7 +269,7 @@ pub fn from_filename<P: AsRef<Path>>(filename: P) -> Result<PathBuf> {
 /// which is preferred.
 ///
 /// ```no_run
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// let iter = dotenv::from_filename_iter(".env").unwrap();
 ///
 /// for item in iter {
@@ -181,7 +287,7 @@ pub fn from_filename_iter<P: AsRef<Path>>(filename: P) -> Result<Iter<File>> {
 ///
 /// # Examples
 /// ```
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 /// dotenv::dotenv().ok();
 /// ```
 pub fn dotenv() -> Result<PathBuf> {
@@ -194,7 +300,7 @@ pub fn dotenv() -> Result<PathBuf> {
 ///
 /// # Examples
 /// ```no_run
-/// use dotenv;
+/// use sd_dotenv as dotenv;
 ///
 /// for item in dotenv::dotenv_iter().unwrap() {
 ///   let (key, val) = item.unwrap();
@@ -205,3 +311,85 @@ pub fn dotenv_iter() -> Result<Iter<File>> {
     let (_, iter) = Finder::new().find()?;
     Ok(iter)
 }
+
+/// Like `from_path`, but reads the file with non-blocking IO.
+///
+/// Examples
+///
+/// ```no_run
+/// # async fn run() {
+/// use sd_dotenv as dotenv;
+///
+/// dotenv::from_path_async("/srv/app/.env").await.ok();
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn from_path_async<P: AsRef<Path>>(path: P) -> Result<()> {
+    from_path_iter_async(path).await?.load()
+}
+
+/// Like `from_path_iter`, but reads the file with non-blocking IO.
+///
+/// The whole file is read up front, so iterating never blocks. For the same reason,
+/// `# @include` directives fail with `Error::AsyncInclude`.
+#[cfg(feature = "async")]
+pub async fn from_path_iter_async<P: AsRef<Path>>(path: P) -> Result<Iter<Cursor<Vec<u8>>>> {
+    let contents = tokio::fs::read(path).await.map_err(Error::Io)?;
+    Ok(Iter::new(Cursor::new(contents)).reject_includes())
+}
+
+/// Like `from_filename`, but searches for and reads the file with non-blocking IO.
+///
+/// # Examples
+/// ```no_run
+/// # async fn run() {
+/// use sd_dotenv as dotenv;
+///
+/// dotenv::from_filename_async("custom.env").await.ok();
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn from_filename_async<P: AsRef<Path>>(filename: P) -> Result<PathBuf> {
+    let (path, iter) = Finder::new()
+        .filename(filename.as_ref())
+        .find_async()
+        .await?;
+    iter.load()?;
+    Ok(path)
+}
+
+/// Like `from_filename_iter`, but searches for and reads the file with non-blocking IO.
+#[cfg(feature = "async")]
+pub async fn from_filename_iter_async<P: AsRef<Path>>(
+    filename: P,
+) -> Result<Iter<Cursor<Vec<u8>>>> {
+    let (_, iter) = Finder::new()
+        .filename(filename.as_ref())
+        .find_async()
+        .await?;
+    Ok(iter)
+}
+
+/// Like `dotenv`, but searches for and reads the .env file with non-blocking IO.
+///
+/// # Examples
+/// ```no_run
+/// # async fn run() {
+/// use sd_dotenv as dotenv;
+///
+/// dotenv::dotenv_async().await.ok();
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub async fn dotenv_async() -> Result<PathBuf> {
+    let (path, iter) = Finder::new().find_async().await?;
+    iter.load()?;
+    Ok(path)
+}
+
+/// Like `dotenv_iter`, but searches for and reads the .env file with non-blocking IO.
+#[cfg(feature = "async")]
+pub async fn dotenv_iter_async() -> Result<Iter<Cursor<Vec<u8>>>> {
+    let (_, iter) = Finder::new().find_async().await?;
+    Ok(iter)
+}
@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::errors::Error;
+use crate::parse::{self, Dialect, ParsedEntry};
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Info => "info",
+        })
+    }
+}
+
+/// A check performed by `lint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    /// The line cannot be parsed.
+    ParseError,
+    /// The key was already defined earlier in the file.
+    DuplicateKey,
+    /// The key contains lowercase letters.
+    LowercaseKey,
+    /// There is whitespace before or after the `=`.
+    SpaceAroundEquals,
+    /// An unquoted value contains an escaped space, which reads better quoted.
+    UnquotedEscapedSpace,
+    /// The line starts with `export`.
+    ExportPrefix,
+    /// A substitution refers to a variable that is not defined earlier in the file.
+    UndefinedSubstitution,
+    /// The file does not end with a newline.
+    MissingTrailingNewline,
+}
+
+impl Rule {
+    pub const ALL: [Self; 8] = [
+        Self::ParseError,
+        Self::DuplicateKey,
+        Self::LowercaseKey,
+        Self::SpaceAroundEquals,
+        Self::UnquotedEscapedSpace,
+        Self::ExportPrefix,
+        Self::UndefinedSubstitution,
+        Self::MissingTrailingNewline,
+    ];
+
+    /// The name of the rule, as used in `# dotenv-lint-disable` comments.
+    #[must_use]
+    pub const fn id(self) -> &'static str {
+        match self {
+            Self::ParseError => "parse-error",
+            Self::DuplicateKey => "duplicate-key",
+            Self::LowercaseKey => "lowercase-key",
+            Self::SpaceAroundEquals => "space-around-equals",
+            Self::UnquotedEscapedSpace => "unquoted-escaped-space",
+            Self::ExportPrefix => "export-prefix",
+            Self::UndefinedSubstitution => "undefined-substitution",
+            Self::MissingTrailingNewline => "missing-trailing-newline",
+        }
+    }
+
+    #[must_use]
+    pub const fn default_severity(self) -> Severity {
+        match self {
+            Self::ParseError => Severity::Error,
+            Self::ExportPrefix => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str(self.id())
+    }
+}
+
+/// The error returned when parsing an unknown rule id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRule(pub String);
+
+impl fmt::Display for UnknownRule {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "unknown lint rule '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRule {}
+
+impl FromStr for Rule {
+    type Err = UnknownRule;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|rule| rule.id() == id)
+            .ok_or_else(|| UnknownRule(id.to_owned()))
+    }
+}
+
+/// A problem found by `lint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// The line the problem is on, starting at 1.
+    pub line: usize,
+    /// The byte range of the problem within the linted source.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "{}:{}: {} [{}]",
+            self.line, self.severity, self.message, self.rule
+        )
+    }
+}
+
+/// Selects which rules `lint` runs and how severe their diagnostics are.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    disabled: HashSet<Rule>,
+    severities: HashMap<Rule, Severity>,
+}
+
+impl LintConfig {
+    /// Creates a configuration with every rule enabled at its default severity.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.disabled.insert(rule);
+        self
+    }
+
+    #[must_use]
+    pub fn severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.severities.insert(rule, severity);
+        self
+    }
+
+    fn severity_of(&self, rule: Rule) -> Severity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_severity())
+    }
+}
+
+const DISABLE: &str = "dotenv-lint-disable";
+const DISABLE_NEXT_LINE: &str = "dotenv-lint-disable-next-line";
+const ENABLE: &str = "dotenv-lint-enable";
+
+/// Rules switched off by comments in the file being linted.
+#[derive(Default)]
+struct Suppressions {
+    all: bool,
+    rules: HashSet<Rule>,
+    next_line_all: bool,
+    next_line: HashSet<Rule>,
+}
+
+impl Suppressions {
+    /// Applies the directive in `comment`, if any. Unknown rule ids are ignored.
+    fn apply(&mut self, comment: &str) {
+        let comment = comment.trim_start_matches('#').trim();
+        let (directive, rules) = comment
+            .split_once(char::is_whitespace)
+            .unwrap_or((comment, ""));
+        let ids: Vec<&str> = rules
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|id| !id.is_empty())
+            .collect();
+        // a directive naming only unknown rules switches nothing off, rather than everything
+        let all = ids.is_empty();
+        let rules: HashSet<Rule> = ids.into_iter().filter_map(|id| id.parse().ok()).collect();
+
+        match directive {
+            DISABLE if all => self.all = true,
+            DISABLE => self.rules.extend(rules),
+            DISABLE_NEXT_LINE if all => self.next_line_all = true,
+            DISABLE_NEXT_LINE => self.next_line.extend(rules),
+            ENABLE if all => *self = Self::default(),
+            ENABLE => self.rules.retain(|rule| !rules.contains(rule)),
+            _ => {}
+        }
+    }
+
+    fn is_suppressed(&self, rule: Rule) -> bool {
+        self.all
+            || self.next_line_all
+            || self.rules.contains(&rule)
+            || self.next_line.contains(&rule)
+    }
+
+    fn end_line(&mut self) {
+        self.next_line_all = false;
+        self.next_line.clear();
+    }
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    suppressions: Suppressions,
+    diagnostics: Vec<Diagnostic>,
+    line: usize,
+    offset: usize,
+}
+
+impl Linter<'_> {
+    /// Reports `rule` on `span`, a byte range within the current line.
+    fn report(&mut self, rule: Rule, span: Range<usize>, message: String) {
+        if self.config.disabled.contains(&rule) || self.suppressions.is_suppressed(rule) {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            rule,
+            severity: self.config.severity_of(rule),
+            line: self.line,
+            span: self.offset + span.start..self.offset + span.end,
+            message,
+        });
+    }
+
+    fn check_entry(&mut self, line: &str, entry: &ParsedEntry, defined: &HashMap<String, usize>) {
+        if let Some(first_line) = defined.get(&entry.key) {
+            self.report(
+                Rule::DuplicateKey,
+                entry.key_span.clone(),
+                format!("'{}' is already defined on line {first_line}", entry.key),
+            );
+        }
+
+        if entry.key.chars().any(|c| c.is_ascii_lowercase()) {
+            self.report(
+                Rule::LowercaseKey,
+                entry.key_span.clone(),
+                format!("'{}' should be upper case", entry.key),
+            );
+        }
+
+        if entry.key_span.end != entry.equals {
+            self.report(
+                Rule::SpaceAroundEquals,
+                entry.key_span.end..entry.equals,
+                "whitespace before '='".to_owned(),
+            );
+        }
+        if !entry.value_span.is_empty() && entry.equals + 1 != entry.value_span.start {
+            self.report(
+                Rule::SpaceAroundEquals,
+                entry.equals + 1..entry.value_span.start,
+                "whitespace after '='".to_owned(),
+            );
+        }
+
+        if let Some(span) = unquoted_escaped_space(&line[entry.value_span.clone()]) {
+            let start = entry.value_span.start;
+            self.report(
+                Rule::UnquotedEscapedSpace,
+                start + span.start..start + span.end,
+                "escaped space in an unquoted value, consider quoting it".to_owned(),
+            );
+        }
+
+        if let Some(span) = &entry.export {
+            self.report(
+                Rule::ExportPrefix,
+                span.clone(),
+                "'export' prefix has no effect".to_owned(),
+            );
+        }
+
+        for substitution in &entry.substitutions {
+            if !defined.contains_key(&substitution.name) {
+                self.report(
+                    Rule::UndefinedSubstitution,
+                    substitution.span.clone(),
+                    format!(
+                        "'{}' is not defined earlier in the file, its value comes from the environment",
+                        substitution.name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Finds the first `\ ` outside of quotes in a raw value.
+fn unquoted_escaped_space(raw: &str) -> Option<Range<usize>> {
+    let mut quote = None;
+    let mut chars = raw.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                if let Some((_, ' ')) = chars.next() {
+                    return Some(index..index + 2);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Checks the contents of a .env file for problems and questionable style.
+///
+/// Beyond the rules in `config`, rules can be switched off from within the file with a
+/// `# dotenv-lint-disable [rule, ...]` comment, which applies until a matching
+/// `# dotenv-lint-enable [rule, ...]`, or with `# dotenv-lint-disable-next-line [rule, ...]`.
+/// Without a list of rules, these comments apply to every rule.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv::{lint, LintConfig, Rule};
+///
+/// let diagnostics = lint("db_url = postgres://localhost\n", &LintConfig::new());
+/// let rules: Vec<Rule> = diagnostics.iter().map(|diagnostic| diagnostic.rule).collect();
+///
+/// assert_eq!(rules, [Rule::LowercaseKey, Rule::SpaceAroundEquals, Rule::SpaceAroundEquals]);
+/// ```
+#[must_use]
+pub fn lint(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        suppressions: Suppressions::default(),
+        diagnostics: Vec::new(),
+        line: 0,
+        offset: 0,
+    };
+    let mut substitution_data = HashMap::new();
+    let mut defined = HashMap::new();
+
+    for line in source.split_inclusive('\n') {
+        linter.line += 1;
+        let content = line.trim_end_matches(['\n', '\r']);
+
+        if content.trim_start().starts_with('#') {
+            linter.suppressions.apply(content.trim_start());
+        } else {
+            match parse::parse_line(content, &substitution_data, Dialect::dotenv()) {
+                Ok(Some(entry)) => {
+                    linter.check_entry(content, &entry, &defined);
+                    substitution_data.insert(entry.key.clone(), Some(entry.value));
+                    defined.entry(entry.key).or_insert(linter.line);
+                }
+                Ok(None) => {}
+                Err(Error::LineParse(err)) => {
+                    let message = err.kind().to_string();
+                    linter.report(Rule::ParseError, err.span(), message);
+                }
+                Err(_) => unreachable!("parsing a line only fails with Error::LineParse"),
+            }
+            linter.suppressions.end_line();
+        }
+
+        linter.offset += line.len();
+    }
+
+    if !source.is_empty() && !source.ends_with('\n') {
+        // the offset is now the end of the source, so the span is relative to it
+        linter.report(
+            Rule::MissingTrailingNewline,
+            0..0,
+            "missing newline at the end of the file".to_owned(),
+        );
+    }
+
+    linter.diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(source: &str) -> Vec<(usize, Rule)> {
+        lint(source, &LintConfig::new())
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_file() {
+        assert!(rules("# comment\nKEY=value\nOTHER=\"${KEY} two\"\n").is_empty());
+    }
+
+    #[test]
+    fn test_rules() {
+        let source = "\
+KEY=1
+KEY=2
+lower=3
+SPACED = 4
+ESCAPED=a\\ b
+QUOTED=\"a\\ b\"
+export EXPORTED=5
+SUBSTITUTED=$UNDEFINED_IN_FILE
+BROKEN=\"open
+LAST=end";
+
+        assert_eq!(
+            rules(source),
+            [
+                (2, Rule::DuplicateKey),
+                (3, Rule::LowercaseKey),
+                (4, Rule::SpaceAroundEquals),
+                (4, Rule::SpaceAroundEquals),
+                (5, Rule::UnquotedEscapedSpace),
+                (7, Rule::ExportPrefix),
+                (8, Rule::UndefinedSubstitution),
+                (9, Rule::ParseError),
+                (10, Rule::MissingTrailingNewline),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans_are_source_offsets() {
+        let source = "A=1\nKEY=1\nKEY=2";
+        let diagnostics = lint(source, &LintConfig::new());
+
+        assert_eq!(diagnostics[0].rule, Rule::DuplicateKey);
+        assert_eq!(&source[diagnostics[0].span.clone()], "KEY");
+        assert_eq!(diagnostics[0].span.start, 10);
+        assert_eq!(diagnostics[1].rule, Rule::MissingTrailingNewline);
+        assert_eq!(diagnostics[1].span, source.len()..source.len());
+    }
+
+    #[test]
+    fn test_config() {
+        let config = LintConfig::new()
+            .disable(Rule::LowercaseKey)
+            .severity(Rule::ExportPrefix, Severity::Error);
+        let diagnostics = lint("lower=1\nexport UPPER=2\n", &config);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, Rule::ExportPrefix);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_disable_comments() {
+        let source = "\
+# dotenv-lint-disable-next-line
+lower=1
+also_lower=2
+# dotenv-lint-disable lowercase-key, export-prefix
+export more_lower=3
+# dotenv-lint-enable lowercase-key
+still_lower=4
+# dotenv-lint-disable
+export LAST=$NOPE
+";
+
+        assert_eq!(
+            rules(source),
+            [(3, Rule::LowercaseKey), (7, Rule::LowercaseKey)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_ids_disable_nothing() {
+        let source = "\
+# dotenv-lint-disable lowercase_key
+lower=1
+# dotenv-lint-disable-next-line no-such-rule
+also_lower=2
+";
+
+        assert_eq!(
+            rules(source),
+            [(2, Rule::LowercaseKey), (4, Rule::LowercaseKey)]
+        );
+    }
+
+    #[test]
+    fn test_rule_ids() {
+        for rule in Rule::ALL {
+            assert_eq!(rule.id().parse::<Rule>(), Ok(rule));
+        }
+        assert!("no-such-rule".parse::<Rule>().is_err());
+    }
+}
@@ -1,39 +1,505 @@
-use crate::errors::{Error, Result};
-use std::{collections::HashMap, mem};
+use crate::errors::{Error, ParseError, ParseErrorKind, Result};
+use std::{collections::HashMap, iter::Peekable, mem, ops::Range, str::CharIndices};
 
 // for readability's sake
-pub type ParsedLine = Result<Option<(String, String)>>;
+pub type ParsedLine = Result<Option<ParsedEntry>>;
+
+/// The syntax that lines are parsed with.
+///
+/// # Examples
+/// ```
+/// use sd_dotenv as dotenv;
+/// use dotenv::Dialect;
+///
+/// let pairs = dotenv::from_str_iter("URL=http://host/#anchor")
+///     .dialect(Dialect::shell_compat())
+///     .collect::<dotenv::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert_eq!(pairs[0].1, "http://host/#anchor");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dialect {
+    kind: DialectKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum DialectKind {
+    #[default]
+    Dotenv,
+    ShellCompat,
+    Rules(DialectRules),
+}
+
+impl Dialect {
+    /// The syntax this crate has always parsed, and the default.
+    #[must_use]
+    pub const fn dotenv() -> Self {
+        Self {
+            kind: DialectKind::Dotenv,
+        }
+    }
+
+    /// Parses values according to `rules`, for files written for other tools.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv as dotenv;
+    /// use dotenv::{Dialect, DialectRules, Expansion};
+    ///
+    /// // like Node's dotenv, but with `${NAME}` expansion
+    /// let rules = DialectRules::node().expansion(Expansion::Braced);
+    /// let pairs = dotenv::from_str_iter("HOST=localhost\nURL=\"http://${HOST}\"")
+    ///     .dialect(Dialect::rules(rules))
+    ///     .collect::<dotenv::Result<Vec<_>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(pairs[1].1, "http://localhost");
+    /// ```
+    #[must_use]
+    pub const fn rules(rules: DialectRules) -> Self {
+        Self {
+            kind: DialectKind::Rules(rules),
+        }
+    }
+
+    /// Parses files the way docker-compose reads an `env_file`, see
+    /// `DialectRules::docker_compose`.
+    #[must_use]
+    pub const fn docker_compose() -> Self {
+        Self::rules(DialectRules::docker_compose())
+    }
+
+    /// Parses files the way the `dotenv` package for Node.js does, see `DialectRules::node`.
+    #[must_use]
+    pub const fn node() -> Self {
+        Self::rules(DialectRules::node())
+    }
+
+    /// Parses files the way `python-dotenv` does, see `DialectRules::python`.
+    #[must_use]
+    pub const fn python() -> Self {
+        Self::rules(DialectRules::python())
+    }
+
+    /// Parses files the way the `dotenv` gem for Ruby does, see `DialectRules::ruby`.
+    #[must_use]
+    pub const fn ruby() -> Self {
+        Self::rules(DialectRules::ruby())
+    }
+
+    /// Whether a quoted value may continue on the following lines.
+    #[must_use]
+    pub const fn multiline(&self) -> bool {
+        matches!(self.kind, DialectKind::Rules(rules) if rules.multiline)
+    }
+
+    /// Parses values exactly like `sh -c 'set -a; . ./.env'` would, for the subset of shell
+    /// syntax that makes sense in a .env file.
+    ///
+    /// Compared to `dotenv`:
+    /// - there must be no whitespace around the `=`
+    /// - `#` only starts a comment at the beginning of a word, so `KEY=a#b` is `a#b`
+    /// - outside quotes, a backslash escapes any character
+    /// - within double quotes, a backslash only escapes `$`, `` ` ``, `"` and `\`
+    /// - variables defined earlier in the file take precedence over the environment
+    ///
+    /// Syntax that the shell would execute or expand in other ways, such as `$(...)`,
+    /// `${NAME:-default}`, `~` or a second word after the value, is rejected with
+    /// `ParseErrorKind::UnsupportedSyntax` or `ParseErrorKind::UnexpectedCharacter`. Values
+    /// cannot span multiple lines. The shell keeps the last definition of a key, which
+    /// `DuplicatePolicy::LastWins` replicates.
+    #[must_use]
+    pub const fn shell_compat() -> Self {
+        Self {
+            kind: DialectKind::ShellCompat,
+        }
+    }
+
+    /// The value a substitution of `name` expands to when the file defines it as `defined`,
+    /// taking the environment into account the same way the parser does.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv::{Dialect, EnvGuard};
+    ///
+    /// let _guard = EnvGuard::capture(["RESOLVE_HOST"]);
+    /// std::env::set_var("RESOLVE_HOST", "from_env");
+    /// let dotenv = Dialect::dotenv().resolve("RESOLVE_HOST", Some("from_file"));
+    /// let shell = Dialect::shell_compat().resolve("RESOLVE_HOST", Some("from_file"));
+    ///
+    /// assert_eq!(dotenv.as_deref(), Some("from_env"));
+    /// assert_eq!(shell.as_deref(), Some("from_file"));
+    /// ```
+    #[must_use]
+    pub fn resolve(&self, name: &str, defined: Option<&str>) -> Option<String> {
+        lookup(&Defined { name, defined }, name, self.prefers_file_values())
+    }
+
+    /// Whether variables defined earlier in the file take precedence over the environment.
+    const fn prefers_file_values(self) -> bool {
+        match self.kind {
+            DialectKind::Dotenv => false,
+            DialectKind::ShellCompat => true,
+            DialectKind::Rules(rules) => rules.prefer_file_values,
+        }
+    }
+}
+
+/// The rules that `Dialect::rules` parses with.
+///
+/// Every preset starts from a tool's behaviour, and each rule can be changed on its own. Keys
+/// may contain any character but whitespace, `=`, `#` and quotes, an `export` prefix is
+/// allowed, and whitespace around the `=` and the value is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialectRules {
+    quotes: Quotes,
+    comments: Comments,
+    escapes: Escapes,
+    expansion: Expansion,
+    prefer_file_values: bool,
+    multiline: bool,
+}
+
+impl DialectRules {
+    /// Values are used verbatim: quotes are kept, there are no inline comments, escapes or
+    /// expansion.
+    #[must_use]
+    pub const fn docker_compose() -> Self {
+        Self {
+            quotes: Quotes::None,
+            comments: Comments::LineOnly,
+            escapes: Escapes::None,
+            expansion: Expansion::None,
+            prefer_file_values: false,
+            multiline: false,
+        }
+    }
+
+    /// Single, double and backtick quotes, which may span lines, `#` comments anywhere after
+    /// an unquoted value, `\n` and `\r` within double quotes, and no expansion.
+    #[must_use]
+    pub const fn node() -> Self {
+        Self {
+            quotes: Quotes::All,
+            comments: Comments::Anywhere,
+            escapes: Escapes::Newlines,
+            expansion: Expansion::None,
+            prefer_file_values: false,
+            multiline: true,
+        }
+    }
+
+    /// Single and double quotes, which may span lines, `#` comments after whitespace,
+    /// Python's escapes, and `${NAME}` expansion that prefers the environment.
+    #[must_use]
+    pub const fn python() -> Self {
+        Self {
+            quotes: Quotes::SingleAndDouble,
+            comments: Comments::AfterWhitespace,
+            escapes: Escapes::Python,
+            expansion: Expansion::Braced,
+            prefer_file_values: false,
+            multiline: true,
+        }
+    }
+
+    /// Single and double quotes, which may span lines, `#` comments anywhere after an
+    /// unquoted value, Ruby's escapes, and `$NAME` expansion that prefers the file.
+    #[must_use]
+    pub const fn ruby() -> Self {
+        Self {
+            quotes: Quotes::SingleAndDouble,
+            comments: Comments::Anywhere,
+            escapes: Escapes::Ruby,
+            expansion: Expansion::Shell,
+            prefer_file_values: true,
+            multiline: true,
+        }
+    }
+
+    /// Sets which quotes are removed from around a value.
+    #[must_use]
+    pub const fn quotes(mut self, quotes: Quotes) -> Self {
+        self.quotes = quotes;
+        self
+    }
+
+    /// Sets where comments may start.
+    #[must_use]
+    pub const fn comments(mut self, comments: Comments) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// Sets which escape sequences are replaced.
+    #[must_use]
+    pub const fn escapes(mut self, escapes: Escapes) -> Self {
+        self.escapes = escapes;
+        self
+    }
+
+    /// Sets the syntax that refers to other variables.
+    #[must_use]
+    pub const fn expansion(mut self, expansion: Expansion) -> Self {
+        self.expansion = expansion;
+        self
+    }
+
+    /// Sets whether expansion prefers variables defined earlier in the file over the
+    /// environment.
+    #[must_use]
+    pub const fn prefer_file_values(mut self, prefer_file_values: bool) -> Self {
+        self.prefer_file_values = prefer_file_values;
+        self
+    }
+
+    /// Sets whether a quoted value may continue on the following lines.
+    #[must_use]
+    pub const fn multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+}
+
+/// Which quotes are removed from around a value.
+///
+/// A quote only counts if it opens the value, and the value then ends at the matching quote.
+/// A quote preceded by a backslash does not end the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Quotes {
+    /// Quotes are part of the value.
+    None,
+    /// `'single'` and `"double"` quotes.
+    SingleAndDouble,
+    /// Single, double and `` `backtick` `` quotes.
+    All,
+}
+
+impl Quotes {
+    const fn contains(self, c: char) -> bool {
+        match self {
+            Self::None => false,
+            Self::SingleAndDouble => c == '\'' || c == '"',
+            Self::All => c == '\'' || c == '"' || c == '`',
+        }
+    }
+}
+
+/// Where comments may start. A line starting with `#` is always a comment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Comments {
+    /// Only whole lines are comments, so `#` is part of any value.
+    LineOnly,
+    /// A `#` preceded by whitespace starts a comment.
+    AfterWhitespace,
+    /// Any `#` after an unquoted value starts a comment.
+    Anywhere,
+}
+
+/// Which escape sequences are replaced. Only double quoted values contain escapes, apart from
+/// `Escapes::Python`, which also replaces `\\` and `\'` within single quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Escapes {
+    /// Backslashes are part of the value.
+    None,
+    /// `\n` and `\r`.
+    Newlines,
+    /// `\a`, `\b`, `\f`, `\n`, `\r`, `\t`, `\v`, `\\`, `\'` and `\"`.
+    Python,
+    /// `\n` and `\r`, and a backslash before any other character is removed.
+    Ruby,
+}
+
+impl Escapes {
+    /// The replacement for the escape sequence `\c` within `quote`, if it is one.
+    const fn unescape(self, c: char, quote: char) -> Option<char> {
+        if quote == '\'' {
+            return match (self, c) {
+                (Self::Python, '\\' | '\'') => Some(c),
+                _ => None,
+            };
+        }
+        if quote != '"' {
+            return None;
+        }
+        match (self, c) {
+            (Self::None, _) => None,
+            (_, 'n') => Some('\n'),
+            (_, 'r') => Some('\r'),
+            (Self::Python, '\\' | '\'' | '"') | (Self::Ruby, _) => Some(c),
+            (Self::Python, 'a') => Some('\u{7}'),
+            (Self::Python, 'b') => Some('\u{8}'),
+            (Self::Python, 'f') => Some('\u{c}'),
+            (Self::Python, 't') => Some('\t'),
+            (Self::Python, 'v') => Some('\u{b}'),
+            _ => None,
+        }
+    }
+}
+
+/// The syntax that refers to other variables, in unquoted and double quoted values.
+///
+/// Variables that are not defined are replaced with an empty string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Expansion {
+    /// `$` is part of the value.
+    None,
+    /// `${NAME}`, and `${NAME:-default}` which uses `default` if `NAME` is not defined.
+    Braced,
+    /// `$NAME` and `${NAME}`, where `\$` is a literal `$`.
+    Shell,
+}
+
+/// The variables defined earlier in a file, which substitutions refer to.
+pub trait Lookup {
+    /// The value `name` was defined with, if any.
+    fn lookup(&self, name: &str) -> Option<&str>;
+
+    /// Whether `name` was removed by an `unset` directive, which also hides the environment's
+    /// value from substitutions.
+    fn is_unset(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// The value of `name` in the environment, which is the process environment unless the
+    /// variables are loaded into an `Env`.
+    fn env_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+/// A single variable, for `Dialect::resolve`.
+struct Defined<'a> {
+    name: &'a str,
+    defined: Option<&'a str>,
+}
+
+impl Lookup for Defined<'_> {
+    fn lookup(&self, name: &str) -> Option<&str> {
+        self.defined.filter(|_| name == self.name)
+    }
+}
+
+/// Keys map to `None` once they are unset.
+impl Lookup for HashMap<String, Option<String>> {
+    fn lookup(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_deref()
+    }
+
+    fn is_unset(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(None))
+    }
+}
 
-pub fn parse_line(
-    line: &str,
-    substitution_data: &mut HashMap<String, Option<String>>,
-) -> ParsedLine {
-    let mut parser = LineParser::new(line, substitution_data);
+/// Parses a single line, substituting variables from `substitution_data` and the environment.
+///
+/// Recording the parsed value in `substitution_data` is left to the caller, which decides what
+/// happens to duplicate keys.
+pub fn parse_line(line: &str, substitution_data: &dyn Lookup, dialect: Dialect) -> ParsedLine {
+    let mut parser = LineParser::new(line, substitution_data, dialect);
     parser.parse_line()
 }
 
+/// A successfully parsed `KEY=value` line. Spans are byte ranges within the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedEntry {
+    pub key: String,
+    pub value: String,
+    /// The `export` prefix, if any.
+    pub export: Option<Range<usize>>,
+    pub key_span: Range<usize>,
+    /// The position of the `=`.
+    pub equals: usize,
+    /// The raw value, excluding surrounding whitespace and any trailing comment.
+    pub value_span: Range<usize>,
+    pub substitutions: Vec<Substitution>,
+}
+
+/// A `$NAME` or `${NAME}` reference within a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub name: String,
+    pub span: Range<usize>,
+}
+
+/// Returns the target of an `# @include <path>` directive, if `line` is one.
+///
+/// The path may be wrapped in single or double quotes.
+pub fn parse_include(line: &str) -> Option<&str> {
+    let rest = line
+        .trim()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("@include")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let target = rest.trim();
+    let target = ['"', '\'']
+        .iter()
+        .find_map(|&quote| target.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(target);
+    (!target.is_empty()).then_some(target)
+}
+
+/// Returns the key of an `unset KEY` directive, if `line` is one.
+///
+/// Only one key can be unset per line, and a `#` comment may follow it.
+pub fn parse_unset(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("unset")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let key = rest.split('#').next()?.trim();
+    is_key(key).then_some(key)
+}
+
+/// Whether `key` can be read back as a key by the default dialect.
+pub fn is_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
 struct LineParser<'a> {
     original_line: &'a str,
-    substitution_data: &'a mut HashMap<String, Option<String>>,
+    substitution_data: &'a dyn Lookup,
+    dialect: Dialect,
     line: &'a str,
     pos: usize,
 }
 
 impl<'a> LineParser<'a> {
-    fn new(
-        line: &'a str,
-        substitution_data: &'a mut HashMap<String, Option<String>>,
-    ) -> LineParser<'a> {
-        LineParser {
+    fn new(line: &'a str, substitution_data: &'a dyn Lookup, dialect: Dialect) -> Self {
+        Self {
             original_line: line,
             substitution_data,
-            line: line.trim_end(), // we don’t want trailing whitespace
+            dialect,
+            line: match dialect.kind {
+                // a trailing escaped space is part of the value
+                DialectKind::ShellCompat => line,
+                _ => line.trim_end(), // we don’t want trailing whitespace
+            },
             pos: 0,
         }
     }
 
-    fn err(&self) -> Error {
-        Error::LineParse(self.original_line.into(), self.pos)
+    fn err(&self, kind: ParseErrorKind, span: Range<usize>) -> Error {
+        Error::LineParse(ParseError::new(kind, self.original_line, span))
+    }
+
+    /// The span of the character at the current position, or an empty span at the end.
+    fn current_span(&self) -> Range<usize> {
+        let len = self.line.chars().next().map_or(0, char::len_utf8);
+        self.pos..self.pos + len
     }
 
     fn parse_line(&mut self) -> ParsedLine {
@@ -42,33 +508,54 @@ impl<'a> LineParser<'a> {
         if self.line.is_empty() || self.line.starts_with('#') {
             return Ok(None);
         }
+        match self.dialect.kind {
+            DialectKind::Dotenv => {}
+            DialectKind::ShellCompat => return self.parse_shell_line(),
+            DialectKind::Rules(rules) => return self.parse_rules_line(rules),
+        }
 
+        let mut key_start = self.pos;
         let mut key = self.parse_key()?;
+        let mut key_span = key_start..self.pos;
+        let mut export = None;
         self.skip_whitespace();
 
         // export can be either an optional prefix or a key itself
-        if key == "export" {
-            // here we check for an optional `=`, below we throw directly when it’s not found.
-            if self.expect_equal().is_err() {
-                key = self.parse_key()?;
-                self.skip_whitespace();
-                self.expect_equal()?;
-            }
-        } else {
-            self.expect_equal()?;
+        if key == "export" && !self.line.is_empty() && !self.line.starts_with('=') {
+            export = Some(key_span);
+            key_start = self.pos;
+            key = self.parse_key()?;
+            key_span = key_start..self.pos;
+            self.skip_whitespace();
         }
+        let equals = self.pos;
+        self.expect_equal()?;
         self.skip_whitespace();
 
         if self.line.is_empty() || self.line.starts_with('#') {
-            self.substitution_data.insert(key.clone(), None);
-            return Ok(Some((key, String::new())));
+            return Ok(Some(ParsedEntry {
+                key,
+                value: String::new(),
+                export,
+                key_span,
+                equals,
+                value_span: self.pos..self.pos,
+                substitutions: Vec::new(),
+            }));
         }
 
-        let parsed_value = parse_value(self.line, self.substitution_data)?;
-        self.substitution_data
-            .insert(key.clone(), Some(parsed_value.clone()));
-
-        Ok(Some((key, parsed_value)))
+        let value_start = self.pos;
+        let state = self.parse_value()?;
+
+        Ok(Some(ParsedEntry {
+            key,
+            value: state.output,
+            export,
+            key_span,
+            equals,
+            value_span: value_start..state.value_end,
+            substitutions: state.substitutions,
+        }))
     }
 
     fn parse_key(&mut self) -> Result<String> {
@@ -76,11 +563,12 @@ impl<'a> LineParser<'a> {
             .line
             .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
         {
-            return Err(self.err());
+            return Err(self.err(ParseErrorKind::InvalidKey, self.current_span()));
         }
+        let dots = self.dialect.kind == DialectKind::Dotenv;
         let index = match self
             .line
-            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || (dots && c == '.')))
         {
             Some(index) => index,
             None => self.line.len(),
@@ -93,7 +581,7 @@ impl<'a> LineParser<'a> {
 
     fn expect_equal(&mut self) -> Result<()> {
         if !self.line.starts_with('=') {
-            return Err(self.err());
+            return Err(self.err(ParseErrorKind::MissingEquals, self.current_span()));
         }
         self.line = &self.line[1..];
         self.pos += 1;
@@ -101,16 +589,555 @@ impl<'a> LineParser<'a> {
     }
 
     fn skip_whitespace(&mut self) {
-        let (pos, line) = self.line.find(|c: char| !c.is_whitespace()).map_or_else(
-            || (self.line.len(), ""),
-            |i| (self.pos + i, &self.line[i..]),
-        );
+        let index = self
+            .line
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(self.line.len());
+
+        self.pos += index;
+        self.line = &self.line[index..];
+    }
+
+    // TODO(brxken128): clean this up 💀
+    #[allow(clippy::too_many_lines)]
+    fn parse_value(&self) -> Result<ValueState> {
+        let input = self.line;
+        let substitution_data = self.substitution_data;
+        let mut state = ValueState::default();
+
+        for (index, c) in input.char_indices() {
+            // position of the current character within the whole line
+            let pos = self.pos + index;
+            let span = pos..pos + c.len_utf8();
+
+            //the regex _should_ already trim whitespace off the end
+            //expecting_end is meant to permit: k=v #comment
+            //without affecting: k=v#comment
+            //and throwing on: k=v w
+            if state.expecting_end {
+                if c == ' ' || c == '\t' {
+                    continue;
+                } else if c == '#' {
+                    break;
+                }
+
+                return Err(self.err(ParseErrorKind::UnexpectedCharacter, span));
+            } else if state.escaped {
+                //TODO I tried handling literal \r but various issues
+                //imo not worth worrying about until there's a use case
+                //(actually handling backslash 0x10 would be a whole other matter)
+                //then there's \v \f bell hex... etc
+                match c {
+                    '\\' | '\'' | '"' | '$' | ' ' => state.append(c),
+                    'n' => state.append('\n'), // handle \n case
+                    _ => {
+                        return Err(self.err(ParseErrorKind::InvalidEscape, pos - 1..span.end));
+                    }
+                }
+
+                state.escaped = false;
+            } else if state.strong_quote {
+                if c == '\'' {
+                    state.strong_quote = false;
+                } else {
+                    state.append(c);
+                }
+            } else if state.substitution_mode != SubstitutionMode::None {
+                if c.is_alphanumeric() {
+                    state.substitution_name.push(c);
+                } else {
+                    match state.substitution_mode {
+                        SubstitutionMode::None => unreachable!(),
+                        SubstitutionMode::Block => {
+                            if c == '{' && state.substitution_name.is_empty() {
+                                state.substitution_mode = SubstitutionMode::EscapedBlock;
+                            } else {
+                                state.substitute(substitution_data, pos);
+                                if c == '$' {
+                                    state.substitution_start = pos;
+                                    state.substitution_mode =
+                                        if !state.strong_quote && !state.escaped {
+                                            SubstitutionMode::Block
+                                        } else {
+                                            SubstitutionMode::None
+                                        }
+                                } else {
+                                    state.substitution_mode = SubstitutionMode::None;
+                                    state.append(c);
+                                }
+                            }
+                        }
+                        SubstitutionMode::EscapedBlock => {
+                            if c == '}' {
+                                state.substitution_mode = SubstitutionMode::None;
+                                state.substitute(substitution_data, span.end);
+                            } else {
+                                state.substitution_name.push(c);
+                            }
+                        }
+                    }
+                }
+            } else if c == '$' {
+                state.substitution_start = pos;
+                state.substitution_mode = if !state.strong_quote && !state.escaped {
+                    SubstitutionMode::Block
+                } else {
+                    SubstitutionMode::None
+                }
+            } else if state.weak_quote {
+                if c == '"' {
+                    state.weak_quote = false;
+                } else if c == '\\' {
+                    state.escaped = true;
+                } else {
+                    state.append(c);
+                }
+            } else if c == '\'' {
+                state.quote_start = pos;
+                state.strong_quote = true;
+            } else if c == '"' {
+                state.quote_start = pos;
+                state.weak_quote = true;
+            } else if c == '\\' {
+                state.escaped = true;
+            } else if c == ' ' || c == '\t' {
+                state.expecting_end = true;
+            } else {
+                state.append(c);
+            }
+
+            if !state.expecting_end {
+                state.value_end = span.end;
+            }
+        }
+
+        //XXX also fail if escaped? or...
+        let end = self.pos + input.len();
+        if state.substitution_mode == SubstitutionMode::EscapedBlock {
+            Err(self.err(
+                ParseErrorKind::UnclosedSubstitution,
+                state.substitution_start..end,
+            ))
+        } else if state.strong_quote || state.weak_quote {
+            Err(self.err(ParseErrorKind::UnterminatedQuote, state.quote_start..end))
+        } else {
+            if state.substitution_mode == SubstitutionMode::Block {
+                state.substitute(substitution_data, state.value_end);
+            }
+            Ok(state)
+        }
+    }
+}
+
+impl LineParser<'_> {
+    fn parse_shell_line(&mut self) -> ParsedLine {
+        let mut key_start = self.pos;
+        let mut key = self.parse_key()?;
+        let mut export = None;
+
+        if key == "export" && self.line.starts_with([' ', '\t']) {
+            export = Some(key_start..self.pos);
+            self.skip_whitespace();
+            key_start = self.pos;
+            key = self.parse_key()?;
+        }
+        let key_span = key_start..self.pos;
+        // `KEY =value` runs a command named KEY
+        let equals = self.pos;
+        self.expect_equal()?;
+
+        let value_start = self.pos;
+        let state = self.parse_shell_value()?;
+
+        Ok(Some(ParsedEntry {
+            key,
+            value: state.output,
+            export,
+            key_span,
+            equals,
+            value_span: value_start..state.value_end,
+            substitutions: state.substitutions,
+        }))
+    }
+
+    /// Parses a single shell word, followed by nothing but whitespace and a comment.
+    fn parse_shell_value(&self) -> Result<ValueState> {
+        let input = self.line;
+        let end = self.pos + input.len();
+        let mut chars = input.char_indices().peekable();
+        let mut state = ValueState {
+            value_end: self.pos,
+            ..ValueState::default()
+        };
+
+        while let Some((index, c)) = chars.next() {
+            let pos = self.pos + index;
+            let span = pos..pos + c.len_utf8();
+
+            match c {
+                ' ' | '\t' => {
+                    // the word ends here, anything but a comment would be a command
+                    let rest = input[index..].trim_start_matches([' ', '\t']);
+                    if rest.is_empty() || rest.starts_with('#') {
+                        break;
+                    }
+                    let start = end - rest.len();
+                    let len = rest.chars().next().map_or(0, char::len_utf8);
+                    return Err(self.err(ParseErrorKind::UnexpectedCharacter, start..start + len));
+                }
+                '\'' => {
+                    state.quote_start = pos;
+                    loop {
+                        match chars.next() {
+                            Some((_, '\'')) => break,
+                            Some((_, c)) => state.append(c),
+                            None => {
+                                return Err(self.err(ParseErrorKind::UnterminatedQuote, pos..end))
+                            }
+                        }
+                    }
+                }
+                '"' => {
+                    state.quote_start = pos;
+                    let closed = loop {
+                        match chars.next() {
+                            Some((_, '"')) => break true,
+                            Some((_, '\\')) => match chars.peek() {
+                                Some(&(_, c @ ('$' | '`' | '"' | '\\'))) => {
+                                    chars.next();
+                                    state.append(c);
+                                }
+                                Some(_) => state.append('\\'),
+                                None => break false,
+                            },
+                            Some((index, '$')) => {
+                                self.shell_substitution(&mut chars, index, &mut state, true)?;
+                            }
+                            Some((index, '`')) => {
+                                let pos = self.pos + index;
+                                return Err(
+                                    self.err(ParseErrorKind::UnsupportedSyntax, pos..pos + 1)
+                                );
+                            }
+                            Some((_, c)) => state.append(c),
+                            None => break false,
+                        }
+                    };
+                    if !closed {
+                        return Err(self.err(ParseErrorKind::UnterminatedQuote, pos..end));
+                    }
+                }
+                '\\' => match chars.next() {
+                    Some((_, c)) => state.append(c),
+                    // a line continuation
+                    None => return Err(self.err(ParseErrorKind::UnsupportedSyntax, span)),
+                },
+                '$' => self.shell_substitution(&mut chars, index, &mut state, false)?,
+                ';' | '&' | '|' | '<' | '>' | '(' | ')' | '`' | '~' => {
+                    return Err(self.err(ParseErrorKind::UnsupportedSyntax, span));
+                }
+                _ => state.append(c),
+            }
+
+            state.value_end = self.pos + chars.peek().map_or(input.len(), |&(index, _)| index);
+        }
+
+        Ok(state)
+    }
+
+    /// Expands the `$` at `index`, whose following characters are still in `chars`.
+    fn shell_substitution(
+        &self,
+        chars: &mut Peekable<CharIndices<'_>>,
+        index: usize,
+        state: &mut ValueState,
+        quoted: bool,
+    ) -> Result<()> {
+        let input = self.line;
+        let start = self.pos + index;
+        state.substitution_start = start;
+
+        let end = match chars.peek() {
+            Some(&(_, '{')) => {
+                chars.next();
+                let close = loop {
+                    match chars.next() {
+                        Some((index, '}')) => break index,
+                        Some((_, c)) => state.substitution_name.push(c),
+                        None => {
+                            return Err(self.err(
+                                ParseErrorKind::UnclosedSubstitution,
+                                start..self.pos + input.len(),
+                            ))
+                        }
+                    }
+                };
+                let end = self.pos + close + 1;
+                if !is_shell_name(&state.substitution_name) {
+                    // `${#NAME}`, `${NAME:-default}` and the like
+                    return Err(self.err(ParseErrorKind::UnsupportedSyntax, start..end));
+                }
+                end
+            }
+            Some(&(_, c)) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = input.len();
+                while let Some(&(index, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        end = index;
+                        break;
+                    }
+                    state.substitution_name.push(c);
+                    chars.next();
+                }
+                self.pos + end
+            }
+            // positional and special parameters, `$(...)`, and `$'...'` in some shells
+            Some(&(_, c))
+                if c.is_ascii_digit()
+                    || "@*#?$!-(".contains(c)
+                    || (!quoted && (c == '\'' || c == '"')) =>
+            {
+                return Err(self.err(
+                    ParseErrorKind::UnsupportedSyntax,
+                    start..start + 1 + c.len_utf8(),
+                ));
+            }
+            // any other `$` is literal
+            _ => {
+                state.append('$');
+                return Ok(());
+            }
+        };
 
-        self.pos += pos;
-        self.line = line;
+        let name = mem::take(&mut state.substitution_name);
+        if let Some(value) = lookup(
+            self.substitution_data,
+            &name,
+            self.dialect.prefers_file_values(),
+        ) {
+            state.output.push_str(&value);
+        }
+        state.substitutions.push(Substitution {
+            name,
+            span: start..end,
+        });
+        Ok(())
     }
 }
 
+impl LineParser<'_> {
+    fn parse_rules_line(&mut self, rules: DialectRules) -> ParsedLine {
+        let mut key_start = self.pos;
+        let mut key = self.parse_rules_key()?;
+        let mut key_span = key_start..self.pos;
+        let mut export = None;
+        self.skip_whitespace();
+
+        if key == "export" && !self.line.is_empty() && !self.line.starts_with('=') {
+            export = Some(key_span);
+            key_start = self.pos;
+            key = self.parse_rules_key()?;
+            key_span = key_start..self.pos;
+            self.skip_whitespace();
+        }
+        let equals = self.pos;
+        self.expect_equal()?;
+        self.skip_whitespace();
+
+        let value_start = self.pos;
+        let state = self.parse_rules_value(rules)?;
+
+        Ok(Some(ParsedEntry {
+            key,
+            value: state.output,
+            export,
+            key_span,
+            equals,
+            value_span: value_start..state.value_end,
+            substitutions: state.substitutions,
+        }))
+    }
+
+    fn parse_rules_key(&mut self) -> Result<String> {
+        let index = self
+            .line
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '#' | '\'' | '"' | '`'))
+            .unwrap_or(self.line.len());
+        if index == 0 {
+            return Err(self.err(ParseErrorKind::InvalidKey, self.current_span()));
+        }
+        self.pos += index;
+        let key = String::from(&self.line[..index]);
+        self.line = &self.line[index..];
+        Ok(key)
+    }
+
+    fn parse_rules_value(&self, rules: DialectRules) -> Result<ValueState> {
+        let input = self.line;
+        let end = self.pos + input.len();
+        let mut state = ValueState {
+            value_end: self.pos,
+            ..ValueState::default()
+        };
+
+        let quote = match input.chars().next() {
+            Some(quote) if rules.quotes.contains(quote) => quote,
+            _ => {
+                let comment = match rules.comments {
+                    Comments::LineOnly => None,
+                    Comments::AfterWhitespace => input
+                        .match_indices('#')
+                        .map(|(index, _)| index)
+                        .find(|&index| input[..index].ends_with(char::is_whitespace)),
+                    Comments::Anywhere => input.find('#'),
+                };
+                let raw = comment.map_or(input, |index| &input[..index]).trim_end();
+                self.expand_rules_value(raw, 0, None, rules, &mut state);
+                state.value_end = self.pos + raw.len();
+                return Ok(state);
+            }
+        };
+
+        let close = match closing_quote(&input[1..], quote).map(|index| index + 1) {
+            Some(close) => close,
+            None => return Err(self.err(ParseErrorKind::UnterminatedQuote, self.pos..end)),
+        };
+
+        let rest = input[close + 1..].trim_start();
+        if !(rest.is_empty() || rules.comments != Comments::LineOnly && rest.starts_with('#')) {
+            let start = end - rest.len();
+            let len = rest.chars().next().map_or(0, char::len_utf8);
+            return Err(self.err(ParseErrorKind::UnexpectedCharacter, start..start + len));
+        }
+
+        self.expand_rules_value(&input[1..close], 1, Some(quote), rules, &mut state);
+        state.value_end = self.pos + close + 1;
+        Ok(state)
+    }
+
+    /// Appends `raw`, found at `offset` within the value, with the escapes and expansions that
+    /// `rules` allow within `quote` replaced.
+    fn expand_rules_value(
+        &self,
+        raw: &str,
+        offset: usize,
+        quote: Option<char>,
+        rules: DialectRules,
+        state: &mut ValueState,
+    ) {
+        let expand = rules.expansion != Expansion::None && quote.map_or(true, |quote| quote == '"');
+        let mut chars = raw.char_indices().peekable();
+
+        while let Some((index, c)) = chars.next() {
+            let rest = &raw[index + c.len_utf8()..];
+            match c {
+                '\\' if expand && rules.expansion == Expansion::Shell && rest.starts_with('$') => {
+                    chars.next();
+                    state.append('$');
+                }
+                '\\' => {
+                    let unescaped = quote
+                        .zip(rest.chars().next())
+                        .and_then(|(quote, next)| rules.escapes.unescape(next, quote));
+                    match unescaped {
+                        Some(unescaped) => {
+                            chars.next();
+                            state.append(unescaped);
+                        }
+                        None => state.append(c),
+                    }
+                }
+                '$' if expand => {
+                    let reference = match rules.expansion {
+                        Expansion::Braced => braced_reference(rest),
+                        _ => shell_reference(rest).map(|(name, len)| (name, None, len)),
+                    };
+                    let (name, default, len) = reference.unwrap_or_default();
+                    // not a reference, so an ordinary `$`
+                    if name.is_empty() {
+                        state.append(c);
+                        continue;
+                    }
+                    while chars.next_if(|&(next, _)| next <= index + len).is_some() {}
+
+                    let value = lookup(
+                        self.substitution_data,
+                        name,
+                        self.dialect.prefers_file_values(),
+                    );
+                    if let Some(value) = value.as_deref().or(default) {
+                        state.output.push_str(value);
+                    }
+                    let start = self.pos + offset + index;
+                    state.substitutions.push(Substitution {
+                        name: name.to_string(),
+                        span: start..start + 1 + len,
+                    });
+                }
+                _ => state.append(c),
+            }
+        }
+    }
+}
+
+/// Parses the `{NAME}` or `{NAME:-default}` following a `$`, returning the name, the default
+/// and the length of the reference.
+fn braced_reference(rest: &str) -> Option<(&str, Option<&str>, usize)> {
+    let inner = rest.strip_prefix('{')?;
+    let close = inner.find('}')?;
+    let (name, default) = match inner[..close].split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (&inner[..close], None),
+    };
+    (!name.is_empty()).then_some((name, default, close + 2))
+}
+
+/// Parses the `NAME` or `{NAME}` following a `$`, returning the name and the length of the
+/// reference.
+fn shell_reference(rest: &str) -> Option<(&str, usize)> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    if let Some(inner) = rest.strip_prefix('{') {
+        let close = inner.find('}')?;
+        let name = &inner[..close];
+        return (!name.is_empty() && name.chars().all(is_name)).then_some((name, close + 2));
+    }
+    let len = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+    (len > 0).then_some((&rest[..len], len))
+}
+
+/// Looks up the value of a variable defined in the environment or earlier in the file.
+fn lookup(substitution_data: &dyn Lookup, name: &str, prefer_file_values: bool) -> Option<String> {
+    if substitution_data.is_unset(name) {
+        return None;
+    }
+    let from_file = || substitution_data.lookup(name).map(str::to_owned);
+    if prefer_file_values {
+        from_file().or_else(|| substitution_data.env_var(name))
+    } else {
+        substitution_data.env_var(name).or_else(from_file)
+    }
+}
+
+/// The index of the quote that closes a value quoted with `quote` in `text`, which follows
+/// the opening quote, skipping over escaped ones.
+///
+/// A line break resets the escape, so a value that continues on the next line can be checked
+/// one line at a time.
+pub fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    text.char_indices().find_map(|(index, c)| {
+        let closes = c == quote && !escaped;
+        escaped = c == '\\' && !escaped;
+        closes.then_some(index)
+    })
+}
+
+/// Whether `name` can be used as a variable name in a POSIX shell, `[A-Za-z_][A-Za-z0-9_]*`.
+pub fn is_shell_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[derive(Eq, PartialEq, Default)]
 enum SubstitutionMode {
     #[default]
@@ -129,158 +1156,58 @@ struct ValueState {
     substitution_mode: SubstitutionMode,
     substitution_name: String,
     output: String,
+    // where the innermost open quote and substitution started, for error spans
+    quote_start: usize,
+    substitution_start: usize,
+    // end of the raw value, before any trailing whitespace or comment
+    value_end: usize,
+    substitutions: Vec<Substitution>,
 }
 
 impl ValueState {
     pub fn append(&mut self, c: char) {
         self.output.push(c);
     }
-}
 
-// TODO(brxken128): clean this up 💀
-#[allow(clippy::too_many_lines)]
-fn parse_value(input: &str, substitution_data: &HashMap<String, Option<String>>) -> Result<String> {
-    let mut state = ValueState::default();
-
-    for (index, c) in input.chars().enumerate() {
-        //the regex _should_ already trim whitespace off the end
-        //expecting_end is meant to permit: k=v #comment
-        //without affecting: k=v#comment
-        //and throwing on: k=v w
-        if state.expecting_end {
-            if c == ' ' || c == '\t' {
-                continue;
-            } else if c == '#' {
-                break;
-            }
-
-            return Err(Error::LineParse(input.to_owned(), index));
-        } else if state.escaped {
-            //TODO I tried handling literal \r but various issues
-            //imo not worth worrying about until there's a use case
-            //(actually handling backslash 0x10 would be a whole other matter)
-            //then there's \v \f bell hex... etc
-            match c {
-                '\\' | '\'' | '"' | '$' | ' ' => state.append(c),
-                'n' => state.append('\n'), // handle \n case
-                _ => {
-                    return Err(Error::LineParse(input.to_owned(), index));
-                }
-            }
-
-            state.escaped = false;
-        } else if state.strong_quote {
-            if c == '\'' {
-                state.strong_quote = false;
-            } else {
-                state.append(c);
-            }
-        } else if state.substitution_mode != SubstitutionMode::None {
-            if c.is_alphanumeric() {
-                state.substitution_name.push(c);
-            } else {
-                match state.substitution_mode {
-                    SubstitutionMode::None => unreachable!(),
-                    SubstitutionMode::Block => {
-                        if c == '{' && state.substitution_name.is_empty() {
-                            state.substitution_mode = SubstitutionMode::EscapedBlock;
-                        } else {
-                            apply_substitution(
-                                substitution_data,
-                                &mem::take(&mut state.substitution_name),
-                                &mut state.output,
-                            );
-                            if c == '$' {
-                                state.substitution_mode = if !state.strong_quote && !state.escaped {
-                                    SubstitutionMode::Block
-                                } else {
-                                    SubstitutionMode::None
-                                }
-                            } else {
-                                state.substitution_mode = SubstitutionMode::None;
-                                state.append(c);
-                            }
-                        }
-                    }
-                    SubstitutionMode::EscapedBlock => {
-                        if c == '}' {
-                            state.substitution_mode = SubstitutionMode::None;
-                            apply_substitution(
-                                substitution_data,
-                                &mem::take(&mut state.substitution_name),
-                                &mut state.output,
-                            );
-                        } else {
-                            state.substitution_name.push(c);
-                        }
-                    }
-                }
-            }
-        } else if c == '$' {
-            state.substitution_mode = if !state.strong_quote && !state.escaped {
-                SubstitutionMode::Block
-            } else {
-                SubstitutionMode::None
-            }
-        } else if state.weak_quote {
-            if c == '"' {
-                state.weak_quote = false;
-            } else if c == '\\' {
-                state.escaped = true;
-            } else {
-                state.append(c);
-            }
-        } else if c == '\'' {
-            state.strong_quote = true;
-        } else if c == '"' {
-            state.weak_quote = true;
-        } else if c == '\\' {
-            state.escaped = true;
-        } else if c == ' ' || c == '\t' {
-            state.expecting_end = true;
-        } else {
-            state.append(c);
+    /// Replaces the pending substitution, which ends at `end`, with its value.
+    fn substitute(&mut self, substitution_data: &dyn Lookup, end: usize) {
+        let name = mem::take(&mut self.substitution_name);
+        apply_substitution(substitution_data, &name, &mut self.output);
+        if !name.is_empty() {
+            self.substitutions.push(Substitution {
+                name,
+                span: self.substitution_start..end,
+            });
         }
     }
-
-    //XXX also fail if escaped? or...
-    if state.substitution_mode == SubstitutionMode::EscapedBlock
-        || state.strong_quote
-        || state.weak_quote
-    {
-        Err(Error::LineParse(
-            input.to_owned(),
-            if input.is_empty() { 0 } else { input.len() - 1 },
-        ))
-    } else {
-        apply_substitution(
-            substitution_data,
-            &mem::take(&mut state.substitution_name),
-            &mut state.output,
-        );
-        Ok(state.output)
-    }
 }
 
 fn apply_substitution(
-    substitution_data: &HashMap<String, Option<String>>,
+    substitution_data: &dyn Lookup,
     substitution_name: &str,
     output: &mut String,
 ) {
-    if let Ok(environment_value) = std::env::var(substitution_name) {
-        output.push_str(&environment_value);
-    } else {
-        substitution_data
-            .get(substitution_name)
-            .map(|x| x.clone().map(|x| output.push_str(&x)));
-    };
+    let prefer_file_values = Dialect::dotenv().prefers_file_values();
+    if let Some(value) = lookup(substitution_data, substitution_name, prefer_file_values) {
+        output.push_str(&value);
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{errors::Error::LineParse, iter::Iter, Result};
+    use crate::errors::{Error::LineParse, ParseError, ParseErrorKind};
+    use std::collections::HashMap;
+
+    use crate::{iter::Iter, lock_env, Dialect, DialectRules, EnvGuard, Result};
 
     fn assert_parsed_string(input_string: &str, expected_parse_result: Vec<(&str, &str)>) {
+        // substitutions fall back to the process environment, which other tests modify
+        let _lock = lock_env();
+        assert_parsed_string_locked(input_string, expected_parse_result);
+    }
+
+    /// Like `assert_parsed_string`, for tests that already hold the environment lock.
+    fn assert_parsed_string_locked(input_string: &str, expected_parse_result: Vec<(&str, &str)>) {
         let actual_iter = Iter::new(input_string.as_bytes());
         let expected_count = &expected_parse_result.len();
 
@@ -517,16 +1444,20 @@ KEY4=h\8u
 
     #[test]
     fn substitute_variable_from_env_variable() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["KEY11"]);
         std::env::set_var("KEY11", "test_user_env");
 
-        assert_parsed_string(r#"KEY=">${KEY11}<""#, vec![("KEY", ">test_user_env<")]);
+        assert_parsed_string_locked(r#"KEY=">${KEY11}<""#, vec![("KEY", ">test_user_env<")]);
     }
 
     #[test]
     fn substitute_variable_env_variable_overrides_dotenv_in_substitution() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["KEY11"]);
         std::env::set_var("KEY11", "test_user_env");
 
-        assert_parsed_string(
+        assert_parsed_string_locked(
             r#"
     KEY11=test_user
     KEY=">${KEY11}<"
@@ -584,49 +1515,288 @@ KEY4=h\8u
             |first_line| assert_eq!(first_line, &(String::from("KEY"), String::from("VALUE"))),
         );
 
-        if let Err(LineParse(second_value, index)) = &parsed_values[1] {
-            assert_eq!(second_value, wrong_value);
-            assert_eq!(*index, wrong_value.len() - 1);
+        match &parsed_values[1] {
+            Err(LineParse(err)) => {
+                assert_eq!(err.kind(), ParseErrorKind::UnclosedSubstitution);
+                assert_eq!(err.line(), format!("    KEY1={wrong_value}"));
+                assert_eq!(&err.line()[err.span()], &wrong_value[1..]);
+            }
+            other => panic!("Expected an unclosed substitution, got {other:?}"),
+        }
+    }
+
+    fn parse_error(input: &str) -> ParseError {
+        match Iter::new(input.as_bytes()).find_map(Result::err) {
+            Some(LineParse(err)) => err,
+            other => panic!("Expected a parse error, got {other:?}"),
         }
     }
 
     #[test]
     fn should_not_allow_dot_as_first_character_of_key() {
-        let wrong_key_value = ".Key=VALUE";
+        let err = parse_error(".Key=VALUE");
+
+        assert_eq!(err.kind(), ParseErrorKind::InvalidKey);
+        assert_eq!(err.line(), ".Key=VALUE");
+        assert_eq!(err.span(), 0..1);
+    }
 
-        let parsed_values: Vec<_> = Iter::new(wrong_key_value.as_bytes()).collect();
+    #[test]
+    fn should_not_parse_illegal_format() {
+        let err = parse_error("<><><>");
 
-        assert_eq!(parsed_values.len(), 1);
+        assert_eq!(err.kind(), ParseErrorKind::InvalidKey);
+        assert_eq!(err.span(), 0..1);
+    }
 
-        if let Err(LineParse(second_value, index)) = &parsed_values[0] {
-            assert_eq!(second_value, wrong_key_value);
-            assert_eq!(*index, 0);
-        }
+    #[test]
+    fn should_not_parse_illegal_escape() {
+        let err = parse_error(r"VALUE=>\f<");
+
+        assert_eq!(err.kind(), ParseErrorKind::InvalidEscape);
+        assert_eq!(&err.line()[err.span()], r"\f");
     }
 
     #[test]
-    fn should_not_parse_illegal_format() {
-        let wrong_format = r"<><><>";
-        let parsed_values: Vec<_> = Iter::new(wrong_format.as_bytes()).collect();
+    fn should_report_missing_equals() {
+        let err = parse_error("  KEY   VALUE");
+        assert_eq!(err.kind(), ParseErrorKind::MissingEquals);
+        assert_eq!(err.span(), 8..9);
+
+        let err = parse_error("export KEY");
+        assert_eq!(err.kind(), ParseErrorKind::MissingEquals);
+        assert_eq!(err.span(), 10..10);
+    }
 
-        assert_eq!(parsed_values.len(), 1);
+    #[test]
+    fn should_report_unterminated_quote() {
+        let err = parse_error(r#"KEY=a"b c"#);
 
-        if let Err(LineParse(wrong_value, index)) = &parsed_values[0] {
-            assert_eq!(wrong_value, wrong_format);
-            assert_eq!(*index, 0);
+        assert_eq!(err.kind(), ParseErrorKind::UnterminatedQuote);
+        assert_eq!(&err.line()[err.span()], r#""b c"#);
+    }
+
+    #[test]
+    fn should_report_unexpected_character() {
+        let err = parse_error("KEY=välue trailing");
+
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedCharacter);
+        assert_eq!(&err.line()[err.span()], "t");
+    }
+
+    fn parse_shell(input: &str) -> Result<Vec<(String, String)>> {
+        let _lock = lock_env();
+        Iter::new(input.as_bytes())
+            .dialect(Dialect::shell_compat())
+            .collect()
+    }
+
+    fn shell_error(input: &str) -> ParseError {
+        match parse_shell(input) {
+            Err(LineParse(err)) => err,
+            other => panic!("Expected a parse error, got {other:?}"),
         }
     }
 
     #[test]
-    fn should_not_parse_illegal_escape() {
-        let wrong_escape = r">\f<";
-        let parsed_values: Vec<_> = Iter::new(format!("VALUE={wrong_escape}").as_bytes()).collect();
+    fn test_shell_compat_values() {
+        let pairs = parse_shell(
+            r#"
+export SHELL_URL=http://host/#anchor # comment
+SHELL_ESCAPED=a\ b\#c\q
+SHELL_SINGLE='$SHELL_URL \n'
+SHELL_DOUBLE="\$ \" \\ \n ${SHELL_ESCAPED}"
+SHELL_MIXED=a'b'"c"$SHELL_ESCAPED$
+SHELL_EMPTY= # nothing
+SHELL_LITERAL=#not-a-comment
+"#,
+        )
+        .unwrap();
+
+        let expected = [
+            ("SHELL_URL", "http://host/#anchor"),
+            ("SHELL_ESCAPED", "a b#cq"),
+            ("SHELL_SINGLE", "$SHELL_URL \\n"),
+            ("SHELL_DOUBLE", "$ \" \\ \\n a b#cq"),
+            ("SHELL_MIXED", "abca b#cq$"),
+            ("SHELL_EMPTY", ""),
+            ("SHELL_LITERAL", "#not-a-comment"),
+        ];
+        assert_eq!(
+            pairs,
+            expected.map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_shell_compat_prefers_file_values() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["SHELL_SHADOWED"]);
+        std::env::set_var("SHELL_SHADOWED", "environment");
 
-        assert_eq!(parsed_values.len(), 1);
+        let pairs: Vec<_> = Iter::new(b"SHELL_SHADOWED=file\nSHELL_COPY=$SHELL_SHADOWED".as_ref())
+            .dialect(Dialect::shell_compat())
+            .collect::<Result<_>>()
+            .unwrap();
 
-        if let Err(LineParse(wrong_value, index)) = &parsed_values[0] {
-            assert_eq!(wrong_value, wrong_escape);
-            assert_eq!(*index, wrong_escape.find('\\').unwrap() + 1);
+        assert_eq!(pairs[1].1, "file");
+    }
+
+    #[test]
+    fn test_shell_compat_rejects_unsupported_syntax() {
+        for (input, span) in [
+            ("KEY=$(whoami)", "$("),
+            ("KEY=`whoami`", "`"),
+            (r#"KEY="`whoami`""#, "`"),
+            ("KEY=${HOME:-/root}", "${HOME:-/root}"),
+            ("KEY=$1", "$1"),
+            ("KEY=~/bin", "~"),
+            ("KEY=a;b", ";"),
+            ("KEY=a\\", "\\"),
+        ] {
+            let err = shell_error(input);
+            assert_eq!(err.kind(), ParseErrorKind::UnsupportedSyntax, "{input}");
+            assert_eq!(&err.line()[err.span()], span, "{input}");
         }
     }
+
+    #[test]
+    fn test_shell_compat_errors() {
+        let err = shell_error("KEY =value");
+        assert_eq!(err.kind(), ParseErrorKind::MissingEquals);
+        assert_eq!(err.span(), 3..4);
+
+        let err = shell_error("KEY=value command");
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedCharacter);
+        assert_eq!(&err.line()[err.span()], "c");
+
+        let err = shell_error("KEY.NAME=value");
+        assert_eq!(err.kind(), ParseErrorKind::MissingEquals);
+
+        let err = shell_error(r#"KEY=a"b\""#);
+        assert_eq!(err.kind(), ParseErrorKind::UnterminatedQuote);
+        assert_eq!(&err.line()[err.span()], r#""b\""#);
+
+        let err = shell_error("KEY=${NAME");
+        assert_eq!(err.kind(), ParseErrorKind::UnclosedSubstitution);
+        assert_eq!(&err.line()[err.span()], "${NAME");
+    }
+
+    fn parse_with(dialect: Dialect, input: &str) -> Result<Vec<(String, String)>> {
+        let _lock = lock_env();
+        Iter::new(input.as_bytes()).dialect(dialect).collect()
+    }
+
+    fn values(pairs: &[(String, String)]) -> Vec<&str> {
+        pairs.iter().map(|(_, value)| value.as_str()).collect()
+    }
+
+    // the same file, read by every preset
+    const DIALECT_INPUT: &str = r#"
+DIALECT_PLAIN = plain value # comment
+DIALECT_HASH=a#b
+DIALECT_SINGLE='single \' $DIALECT_HASH'
+DIALECT_DOUBLE="double\n\t\$ ${DIALECT_HASH}"
+export DIALECT_BARE=$DIALECT_HASH ${DIALECT_UNSET:-default}
+"#;
+
+    #[test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn test_docker_compose_dialect() {
+        let pairs = parse_with(Dialect::docker_compose(), DIALECT_INPUT).unwrap();
+
+        assert_eq!(
+            values(&pairs),
+            [
+                "plain value # comment",
+                "a#b",
+                r"'single \' $DIALECT_HASH'",
+                r#""double\n\t\$ ${DIALECT_HASH}""#,
+                "$DIALECT_HASH ${DIALECT_UNSET:-default}",
+            ]
+        );
+    }
+
+    #[test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn test_node_dialect() {
+        let pairs = parse_with(Dialect::node(), DIALECT_INPUT).unwrap();
+
+        assert_eq!(
+            values(&pairs),
+            [
+                "plain value",
+                "a",
+                r"single \' $DIALECT_HASH",
+                "double\n\\t\\$ ${DIALECT_HASH}",
+                "$DIALECT_HASH ${DIALECT_UNSET:-default}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_python_dialect() {
+        let pairs = parse_with(Dialect::python(), DIALECT_INPUT).unwrap();
+
+        assert_eq!(
+            values(&pairs),
+            [
+                "plain value",
+                "a#b",
+                "single ' $DIALECT_HASH",
+                "double\n\t\\$ a#b",
+                "$DIALECT_HASH default",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ruby_dialect() {
+        let pairs = parse_with(Dialect::ruby(), DIALECT_INPUT).unwrap();
+
+        assert_eq!(
+            values(&pairs),
+            [
+                "plain value",
+                "a",
+                r"single \' $DIALECT_HASH",
+                "double\nt$ a",
+                "a ${DIALECT_UNSET:-default}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiline_values() {
+        let input = "DIALECT_MULTI=\"first\nsecond\" # comment\nDIALECT_NEXT=1\n";
+
+        let pairs = parse_with(Dialect::node(), input).unwrap();
+        assert_eq!(values(&pairs), ["first\nsecond", "1"]);
+
+        let err = parse_with(Dialect::node(), "DIALECT_OPEN='never closed\nA=1");
+        assert!(
+            matches!(err, Err(LineParse(err)) if err.kind() == ParseErrorKind::UnterminatedQuote)
+        );
+
+        let rules = DialectRules::node().multiline(false);
+        assert!(parse_with(Dialect::rules(rules), input).is_err());
+    }
+
+    #[test]
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn test_rules_errors_and_spans() {
+        let err = match parse_with(Dialect::python(), r#"KEY="value" trailing"#) {
+            Err(LineParse(err)) => err,
+            other => panic!("Expected a parse error, got {other:?}"),
+        };
+        assert_eq!(err.kind(), ParseErrorKind::UnexpectedCharacter);
+        assert_eq!(&err.line()[err.span()], "t");
+
+        let line = "KEY = \"a ${NAME:-b} c\" # comment";
+        let entry = super::parse_line(line, &HashMap::new(), Dialect::python())
+            .unwrap()
+            .unwrap();
+        assert_eq!(&line[entry.value_span], "\"a ${NAME:-b} c\"");
+        assert_eq!(&line[entry.substitutions[0].span.clone()], "${NAME:-b}");
+    }
 }
@@ -0,0 +1,188 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::errors::Result;
+
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Restores the environment variables it tracks to their previous values when dropped.
+///
+/// Variables that were not set before are removed again. A guard is returned by
+/// `load_scoped` and `Iter::load_scoped`, and can be created for arbitrary keys with
+/// `EnvGuard::capture`.
+#[derive(Debug, Default)]
+#[must_use = "the environment is restored as soon as the guard is dropped"]
+pub struct EnvGuard {
+    previous: Vec<(OsString, Option<OsString>)>,
+}
+
+impl EnvGuard {
+    /// Records the current values of `keys`, so that any later change to them is undone when
+    /// the guard is dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use sd_dotenv::EnvGuard;
+    /// use std::env;
+    ///
+    /// {
+    ///     let _guard = EnvGuard::capture(["CAPTURED_KEY"]);
+    ///     env::set_var("CAPTURED_KEY", "temporary");
+    /// }
+    /// assert!(env::var("CAPTURED_KEY").is_err());
+    /// ```
+    pub fn capture<I, K>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<OsString>,
+    {
+        let mut guard = Self::default();
+        for key in keys {
+            guard.record(key.into());
+        }
+        guard
+    }
+
+    /// Records the current value of `key`, unless it is already tracked.
+    pub(crate) fn record(&mut self, key: OsString) {
+        if self.previous.iter().all(|(tracked, _)| *tracked != key) {
+            let value = env::var_os(&key);
+            self.previous.push((key, value));
+        }
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+        }
+    }
+}
+
+/// Serializes access to the process environment, released when dropped.
+///
+/// The environment and the current directory are shared by every thread of the process, so
+/// tests that change them should hold this lock for as long as their `EnvGuard`s are alive.
+/// The current directory is restored when the lock is released.
+#[derive(Debug)]
+#[must_use = "the lock is released as soon as it is dropped"]
+pub struct EnvLock {
+    previous_dir: Option<PathBuf>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Drop for EnvLock {
+    fn drop(&mut self) {
+        if let Some(dir) = self.previous_dir.take() {
+            let _ = env::set_current_dir(dir);
+        }
+    }
+}
+
+/// Acquires the global environment lock, blocking until it is available.
+///
+/// This is meant for tests. Nothing in this crate takes the lock by itself, so it only
+/// serializes the code that does, and it is no substitute for keeping `std::env::set_var` away
+/// from threads that may read the environment. A test that panicked while holding the lock
+/// does not poison it for the others.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv::{load_scoped, lock_env};
+///
+/// let _lock = lock_env();
+/// let _guard = load_scoped("tests/fixtures/.env").unwrap();
+/// // the variables are removed again before the lock is released
+/// ```
+pub fn lock_env() -> EnvLock {
+    let guard = ENV_LOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    EnvLock {
+        // unavailable if the directory was deleted, which leaves nothing worth restoring
+        previous_dir: env::current_dir().ok(),
+        _guard: guard,
+    }
+}
+
+/// Like `from_path`, but restores every variable it sets once the returned guard is dropped.
+pub fn load_scoped<P: AsRef<Path>>(path: P) -> Result<EnvGuard> {
+    crate::from_path_iter(path)?.load_scoped()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+    use crate::iter::Iter;
+
+    #[test]
+    fn test_guard_restores_previous_values() {
+        let _lock = lock_env();
+        let _outer = EnvGuard::capture(["SCOPED_EXISTING", "SCOPED_NEW"]);
+        env::set_var("SCOPED_EXISTING", "before");
+        env::remove_var("SCOPED_NEW");
+
+        {
+            let _guard = EnvGuard::capture(["SCOPED_EXISTING", "SCOPED_NEW"]);
+            env::set_var("SCOPED_EXISTING", "during");
+            env::set_var("SCOPED_NEW", "during");
+        }
+
+        assert_eq!(env::var("SCOPED_EXISTING").unwrap(), "before");
+        assert!(env::var("SCOPED_NEW").is_err());
+    }
+
+    #[test]
+    fn test_load_scoped_only_restores_what_it_set() {
+        let _lock = lock_env();
+        let _preset = EnvGuard::capture(["SCOPED_PRESET", "SCOPED_LOADED"]);
+        env::set_var("SCOPED_PRESET", "kept");
+
+        {
+            let _guard = Iter::new(b"SCOPED_PRESET=ignored\nSCOPED_LOADED=1".as_ref())
+                .load_scoped()
+                .unwrap();
+            assert_eq!(env::var("SCOPED_PRESET").unwrap(), "kept");
+            assert_eq!(env::var("SCOPED_LOADED").unwrap(), "1");
+        }
+
+        assert_eq!(env::var("SCOPED_PRESET").unwrap(), "kept");
+        assert!(env::var("SCOPED_LOADED").is_err());
+    }
+
+    #[test]
+    fn test_load_scoped_restores_on_error() {
+        let _lock = lock_env();
+
+        let result = Iter::new(b"SCOPED_PARTIAL=1\n=invalid".as_ref()).load_scoped();
+
+        assert!(result.is_err());
+        assert!(env::var("SCOPED_PARTIAL").is_err());
+    }
+
+    #[test]
+    fn test_lock_restores_current_dir() {
+        let original = {
+            let _lock = lock_env();
+            env::current_dir().unwrap()
+        };
+
+        {
+            let _lock = lock_env();
+            let dir = tempfile::TempDir::new().unwrap();
+            env::set_current_dir(&dir).unwrap();
+            // the directory is deleted before the lock is released
+        }
+
+        let _lock = lock_env();
+        assert_eq!(env::current_dir().unwrap(), original);
+    }
+}
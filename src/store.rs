@@ -0,0 +1,270 @@
+use std::collections::hash_map::{self, HashMap};
+use std::collections::HashSet;
+use std::error;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::errors::{Error, Result};
+use crate::find::Finder;
+use crate::iter::Iter;
+
+/// An in-process environment that holds loaded variables without touching the environment of
+/// the current process.
+///
+/// `Iter::load` relies on `std::env::set_var`, which is not safe to call while other threads
+/// may be reading the environment. `Env` keeps the loaded variables in a map instead and only
+/// reads from the process environment, as a fallback for keys it does not hold. Keys removed
+/// by `unset` directives are remembered, and hide the process environment's value.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv::Env;
+///
+/// let env = Env::dotenv().unwrap();
+/// let port: u16 = env.get_parsed("PORT").unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    // unset keys map to `None`
+    vars: HashMap<String, Option<String>>,
+}
+
+impl Env {
+    /// Creates an empty store, which only falls back to the process environment.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the file at the specified path into a new store.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut env = Self::new();
+        env.load(crate::from_path_iter(path)?)?;
+        Ok(env)
+    }
+
+    /// Loads the specified file from the current directory or its parents into a new store.
+    pub fn from_filename<P: AsRef<Path>>(filename: P) -> Result<Self> {
+        let (_, iter) = Finder::new().filename(filename.as_ref()).find()?;
+        let mut env = Self::new();
+        env.load(iter)?;
+        Ok(env)
+    }
+
+    /// Loads the .env file from the current directory or its parents into a new store.
+    pub fn dotenv() -> Result<Self> {
+        let (_, iter) = Finder::new().find()?;
+        let mut env = Self::new();
+        env.load(iter)?;
+        Ok(env)
+    }
+
+    /// Adds the variables of `iter` to the store.
+    ///
+    /// Like `Iter::load`, variables that were already present in the store are kept, and keys
+    /// unset by `unset` directives are removed from it. `get` no longer falls back to the
+    /// process environment for those, as if they were removed from it too. Substitutions see
+    /// the variables in the store before the process environment, like `get`.
+    pub fn load<R: Read>(&mut self, iter: Iter<R>) -> Result<()> {
+        let mut loaded = HashSet::new();
+        for item in iter.environment(self.vars.clone()).changes() {
+            let (key, value) = match item? {
+                (key, Some(value)) => (key, value),
+                (key, None) => {
+                    self.vars.insert(key, None);
+                    continue;
+                }
+            };
+            if loaded.contains(&key) || !matches!(self.vars.get(&key), Some(Some(_))) {
+                self.vars.insert(key.clone(), Some(value));
+                loaded.insert(key);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the variable `key` from the store, falling back to the process environment
+    /// unless the key was unset.
+    pub fn get(&self, key: &str) -> Result<String> {
+        match self.vars.get(key) {
+            Some(Some(value)) => Ok(value.clone()),
+            Some(None) => Err(Error::EnvVar(std::env::VarError::NotPresent)),
+            None => std::env::var(key).map_err(Error::EnvVar),
+        }
+    }
+
+    /// Like `get`, but parses the value into `T`.
+    pub fn get_parsed<T>(&self, key: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: error::Error + Send + Sync + 'static,
+    {
+        self.get(key)?
+            .parse()
+            .map_err(|err| Error::InvalidValue(key.to_owned(), Box::new(err)))
+    }
+
+    /// Returns an iterator over the variables held by the store.
+    ///
+    /// The process environment is not included.
+    #[must_use]
+    pub fn iter(&self) -> EnvIter<'_> {
+        EnvIter {
+            inner: self.vars.iter(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+impl<'a> IntoIterator for &'a Env {
+    type Item = (&'a str, &'a str);
+    type IntoIter = EnvIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the variables held by an `Env`, created by `Env::iter`.
+pub struct EnvIter<'a> {
+    inner: hash_map::Iter<'a, String, Option<String>>,
+}
+
+impl<'a> Iterator for EnvIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .find_map(|(key, value)| Some((key.as_str(), value.as_deref()?)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+    use crate::{lock_env, EnvGuard};
+
+    fn store(input: &str) -> Env {
+        let mut env = Env::new();
+        env.load(Iter::new(input.as_bytes())).unwrap();
+        env
+    }
+
+    #[test]
+    fn test_get_does_not_touch_process_env() {
+        let env = store("STORE_ONLY_KEY=stored");
+
+        assert_eq!(env.get("STORE_ONLY_KEY").unwrap(), "stored");
+        assert!(env::var("STORE_ONLY_KEY").is_err());
+    }
+
+    #[test]
+    fn test_get_falls_back_to_process_env() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["STORE_FALLBACK_KEY"]);
+        env::set_var("STORE_FALLBACK_KEY", "from_process");
+        let env = store("OTHER=1");
+
+        assert_eq!(env.get("STORE_FALLBACK_KEY").unwrap(), "from_process");
+        assert!(matches!(
+            env.get("STORE_MISSING_KEY"),
+            Err(Error::EnvVar(env::VarError::NotPresent))
+        ));
+    }
+
+    #[test]
+    fn test_load_keeps_first_value() {
+        let mut env = store("KEY=first");
+        env.load(Iter::new(b"KEY=second\nNEW=1".as_ref())).unwrap();
+
+        assert_eq!(env.get("KEY").unwrap(), "first");
+        assert_eq!(env.len(), 2);
+    }
+
+    #[test]
+    fn test_load_removes_unset_keys() {
+        let mut env = store("KEY=first\nKEPT=1");
+        let iter = Iter::new(b"unset KEY".as_ref()).unset_directives(true);
+        env.load(iter).unwrap();
+
+        assert_eq!(env.len(), 1);
+        assert_eq!(env.get("KEPT").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_unset_keys_hide_process_env() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["STORE_UNSET_KEY"]);
+        env::set_var("STORE_UNSET_KEY", "from_process");
+        let iter = Iter::new(b"unset STORE_UNSET_KEY".as_ref()).unset_directives(true);
+        let mut env = Env::new();
+        env.load(iter).unwrap();
+
+        assert!(env.is_empty());
+        assert!(matches!(
+            env.get("STORE_UNSET_KEY"),
+            Err(Error::EnvVar(env::VarError::NotPresent))
+        ));
+        assert_eq!(env::var("STORE_UNSET_KEY").unwrap(), "from_process");
+
+        // a later file can define it again
+        env.load(Iter::new(b"STORE_UNSET_KEY=again".as_ref()))
+            .unwrap();
+        assert_eq!(env.get("STORE_UNSET_KEY").unwrap(), "again");
+    }
+
+    #[test]
+    fn test_substitutions_see_stored_values() {
+        let _lock = lock_env();
+        let _guard = EnvGuard::capture(["STORE_SUB_SHADOWED", "STORE_SUB_UNSET"]);
+        env::set_var("STORE_SUB_SHADOWED", "from_process");
+        env::set_var("STORE_SUB_UNSET", "from_process");
+        let iter =
+            Iter::new(b"STORE_SUB_A=a\nunset STORE_SUB_UNSET".as_ref()).unset_directives(true);
+        let mut env = store("STORE_SUB_SHADOWED=from_store");
+        env.load(iter).unwrap();
+
+        env.load(Iter::new(
+            b"STORE_SUB_B=${STORE_SUB_A}\nSTORE_SUB_C=${STORE_SUB_SHADOWED}\n\
+              STORE_SUB_D=${STORE_SUB_UNSET}"
+                .as_ref(),
+        ))
+        .unwrap();
+        assert_eq!(env.get("STORE_SUB_B").unwrap(), "a");
+        assert_eq!(env.get("STORE_SUB_C").unwrap(), "from_store");
+        assert_eq!(env.get("STORE_SUB_D").unwrap(), "");
+    }
+
+    #[test]
+    fn test_get_parsed() {
+        let env = store("PORT=8080\nNAME=app");
+
+        assert_eq!(env.get_parsed::<u16>("PORT").unwrap(), 8080);
+        assert!(matches!(
+            env.get_parsed::<u16>("NAME"),
+            Err(Error::InvalidValue(key, _)) if key == "NAME"
+        ));
+    }
+
+    #[test]
+    fn test_iter() {
+        let env = store("A=1\nB=2");
+        let mut pairs: Vec<(&str, &str)> = env.iter().collect();
+        pairs.sort_unstable();
+
+        assert_eq!(pairs, [("A", "1"), ("B", "2")]);
+    }
+}
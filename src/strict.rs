@@ -0,0 +1,267 @@
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use rustix::fs::{Mode, OFlags};
+
+use crate::errors::{Error, Result};
+
+/// Why strict mode refused to load a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsafeReason {
+    /// The file, or a symlink to it, resolves to this path outside of the directory it was
+    /// loaded from.
+    OutsideDirectory(PathBuf),
+    /// The file is owned by this user id rather than the current user.
+    ForeignOwner(u32),
+    /// Any user can write to the file, which has these permission bits.
+    WorldWritable(u32),
+    /// Any user can read the file, which has these permission bits.
+    WorldReadable(u32),
+}
+
+impl fmt::Display for UnsafeReason {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutsideDirectory(target) => {
+                write!(
+                    fmt,
+                    "it resolves to '{}', outside of its directory",
+                    target.display()
+                )
+            }
+            Self::ForeignOwner(owner) => write!(fmt, "it is owned by another user ({owner})"),
+            Self::WorldWritable(mode) => write!(fmt, "it is writable by anyone (mode {mode:o})"),
+            Self::WorldReadable(mode) => write!(fmt, "it is readable by anyone (mode {mode:o})"),
+        }
+    }
+}
+
+/// Opens the file at `path` for reading if it is safe to load secrets from, as done in strict
+/// mode.
+///
+/// The file must resolve to a path inside `directory`, so it cannot be a symlink to a file
+/// elsewhere. On Unix, it must also be owned by the current user and be neither readable nor
+/// writable by other users, like a private SSH key. A file that fails a check is refused with
+/// `Error::UnsafeFile`.
+///
+/// The checks apply to the opened file itself, so it cannot be swapped for another file
+/// between checking and reading it. Read it through the returned handle.
+///
+/// # Examples
+/// ```no_run
+/// use sd_dotenv::{self as dotenv, Iter};
+///
+/// let file = dotenv::open_checked("/srv/app/.env", "/srv/app").unwrap();
+/// Iter::new(file)
+///     .with_path("/srv/app/.env")
+///     .strict(true)
+///     .load()
+///     .unwrap();
+/// ```
+pub fn open_checked<P: AsRef<Path>, Q: AsRef<Path>>(path: P, directory: Q) -> Result<File> {
+    let path = path.as_ref();
+    let target = fs::canonicalize(path).map_err(Error::Io)?;
+    let directory = fs::canonicalize(directory).map_err(Error::Io)?;
+    let file = open_target(&target).map_err(Error::Io)?;
+    let metadata = file.metadata().map_err(Error::Io)?;
+    check(path, &metadata, &target, &directory)?;
+    Ok(file)
+}
+
+/// Like `open_checked`, but opens the file with non-blocking IO.
+#[cfg(feature = "async")]
+pub async fn open_checked_async(path: &Path, directory: &Path) -> Result<tokio::fs::File> {
+    let target = tokio::fs::canonicalize(path).await.map_err(Error::Io)?;
+    let directory = tokio::fs::canonicalize(directory)
+        .await
+        .map_err(Error::Io)?;
+    let opened = target.clone();
+    let file = tokio::task::spawn_blocking(move || open_target(&opened))
+        .await
+        .map_err(|err| Error::Io(err.into()))?
+        .map_err(Error::Io)?;
+    let file = tokio::fs::File::from_std(file);
+    let metadata = file.metadata().await.map_err(Error::Io)?;
+    check(path, &metadata, &target, &directory)?;
+    Ok(file)
+}
+
+/// Opens a canonical path for reading, which fails if a symlink has been put in its place
+/// since it was resolved.
+#[cfg(unix)]
+fn open_target(target: &Path) -> io::Result<File> {
+    let flags = OFlags::RDONLY | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    Ok(File::from(rustix::fs::open(target, flags, Mode::empty())?))
+}
+
+#[cfg(not(unix))]
+fn open_target(target: &Path) -> io::Result<File> {
+    File::open(target)
+}
+
+/// Checks a file from its `metadata` and canonical `target`, which must be inside the
+/// canonical `directory`.
+fn check(path: &Path, metadata: &Metadata, target: &Path, directory: &Path) -> Result<()> {
+    reason(metadata, target, directory).map_or(Ok(()), |reason| {
+        Err(Error::UnsafeFile(path.to_owned(), reason))
+    })
+}
+
+fn reason(metadata: &Metadata, target: &Path, directory: &Path) -> Option<UnsafeReason> {
+    if !target.starts_with(directory) {
+        return Some(UnsafeReason::OutsideDirectory(target.to_owned()));
+    }
+    unix_reason(metadata)
+}
+
+#[cfg(unix)]
+fn unix_reason(metadata: &Metadata) -> Option<UnsafeReason> {
+    let mode = metadata.mode() & 0o7777;
+    if metadata.uid() != rustix::process::geteuid().as_raw() {
+        Some(UnsafeReason::ForeignOwner(metadata.uid()))
+    } else if mode & 0o002 != 0 {
+        Some(UnsafeReason::WorldWritable(mode))
+    } else if mode & 0o004 != 0 {
+        Some(UnsafeReason::WorldReadable(mode))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+const fn unix_reason(_metadata: &Metadata) -> Option<UnsafeReason> {
+    None
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn write(path: &Path, mode: u32) {
+        fs::write(path, "STRICT_KEY=secret").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn refusal(path: &Path, directory: &Path) -> Option<UnsafeReason> {
+        match open_checked(path, directory) {
+            Ok(_) => None,
+            Err(Error::UnsafeFile(refused, reason)) => {
+                assert_eq!(refused, path);
+                Some(reason)
+            }
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn test_permissions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+
+        write(&path, 0o600);
+        assert_eq!(refusal(&path, dir.path()), None);
+        write(&path, 0o640);
+        assert_eq!(refusal(&path, dir.path()), None);
+        write(&path, 0o644);
+        assert_eq!(
+            refusal(&path, dir.path()),
+            Some(UnsafeReason::WorldReadable(0o644))
+        );
+        write(&path, 0o662);
+        assert_eq!(
+            refusal(&path, dir.path()),
+            Some(UnsafeReason::WorldWritable(0o662))
+        );
+    }
+
+    #[test]
+    fn test_symlinks() {
+        let project = TempDir::new().unwrap();
+        let elsewhere = TempDir::new().unwrap();
+        let inside = project.path().join("secrets.env");
+        let outside = elsewhere.path().join("secrets.env");
+        write(&inside, 0o600);
+        write(&outside, 0o600);
+
+        let link = project.path().join(".env");
+        symlink(&inside, &link).unwrap();
+        assert_eq!(refusal(&link, project.path()), None);
+
+        fs::remove_file(&link).unwrap();
+        symlink(&outside, &link).unwrap();
+        assert_eq!(
+            refusal(&link, project.path()),
+            Some(UnsafeReason::OutsideDirectory(
+                outside.canonicalize().unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_foreign_owner() {
+        // only root can give a file away, so other users check a file that root owns instead
+        if rustix::process::geteuid().is_root() {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join(".env");
+            write(&path, 0o600);
+            std::os::unix::fs::chown(&path, Some(65534), None).unwrap();
+            assert_eq!(
+                refusal(&path, dir.path()),
+                Some(UnsafeReason::ForeignOwner(65534))
+            );
+        } else {
+            assert_eq!(
+                refusal(Path::new("/etc/passwd"), Path::new("/etc")),
+                Some(UnsafeReason::ForeignOwner(0))
+            );
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_open_checked_async() {
+        use tokio::io::AsyncReadExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        write(&path, 0o644);
+        let refused = open_checked_async(&path, dir.path()).await;
+        write(&path, 0o600);
+        let mut contents = String::new();
+        open_checked_async(&path, dir.path())
+            .await
+            .unwrap()
+            .read_to_string(&mut contents)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            refused,
+            Err(Error::UnsafeFile(_, UnsafeReason::WorldReadable(0o644)))
+        ));
+        assert_eq!(contents, "STRICT_KEY=secret");
+    }
+
+    #[test]
+    fn test_display() {
+        let err = Error::UnsafeFile(
+            PathBuf::from("/app/.env"),
+            UnsafeReason::WorldReadable(0o644),
+        );
+        assert_eq!(
+            err.to_string(),
+            "Refusing to load '/app/.env': it is readable by anyone (mode 644)"
+        );
+    }
+}
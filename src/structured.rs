@@ -0,0 +1,337 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::vec;
+
+use crate::errors::{Error, Result};
+use crate::iter::load_methods;
+
+/// Joins the keys of nested maps and the indexes of arrays.
+const SEPARATOR: &str = "__";
+
+/// A JSON, YAML or TOML value, reduced to what can be flattened into variables.
+enum Node {
+    Map(Vec<(String, Self)>),
+    List(Vec<Self>),
+    Scalar(String),
+}
+
+/// The variables of a JSON, YAML or TOML document.
+///
+/// Nested keys are joined with `__` and arrays are indexed from 0, so
+/// `{"db": {"hosts": ["a", "b"]}}` becomes `db__hosts__0=a` and `db__hosts__1=b`. Numbers and
+/// booleans are written as they appear in the document, and `null` becomes an empty string.
+/// Like `Iter`, it yields the pairs and loads them with the same rules for variables that are
+/// already set.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "json")] {
+/// use sd_dotenv::StructuredIter;
+///
+/// let pairs = StructuredIter::json(r#"{"db": {"port": 5432, "hosts": ["a", "b"]}}"#)
+///     .unwrap()
+///     .collect::<sd_dotenv::Result<Vec<_>>>()
+///     .unwrap();
+///
+/// assert_eq!(pairs[0], ("db__hosts__0".to_string(), "a".to_string()));
+/// assert_eq!(pairs[2], ("db__port".to_string(), "5432".to_string()));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct StructuredIter {
+    pairs: vec::IntoIter<(String, String)>,
+}
+
+impl StructuredIter {
+    /// Flattens a JSON document, whose top level must be an object or an array.
+    #[cfg(feature = "json")]
+    pub fn json(source: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(source).map_err(|err| Error::InvalidDocument(Box::new(err)))?;
+        Self::flatten(json_node(value))
+    }
+
+    /// Flattens a YAML document, whose top level must be a mapping or a sequence.
+    #[cfg(feature = "yaml")]
+    pub fn yaml(source: &str) -> Result<Self> {
+        let value: serde_yaml::Value =
+            serde_yaml::from_str(source).map_err(|err| Error::InvalidDocument(Box::new(err)))?;
+        Self::flatten(yaml_node(value)?)
+    }
+
+    /// Flattens a TOML document.
+    #[cfg(feature = "toml")]
+    pub fn toml(source: &str) -> Result<Self> {
+        let table: toml::Table = source
+            .parse()
+            .map_err(|err| Error::InvalidDocument(Box::new(err)))?;
+        Self::flatten(toml_node(toml::Value::Table(table)))
+    }
+
+    fn flatten(node: Node) -> Result<Self> {
+        if let Node::Scalar(_) = node {
+            return Err(Error::InvalidDocument(Box::from(
+                "the top level must be a map or an array",
+            )));
+        }
+
+        let mut pairs = Vec::new();
+        flatten_into(&mut pairs, String::new(), node)?;
+        Ok(Self {
+            pairs: pairs.into_iter(),
+        })
+    }
+
+    load_methods!(self);
+}
+
+impl Iterator for StructuredIter {
+    type Item = Result<(String, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pairs.next().map(Ok)
+    }
+}
+
+/// Like `from_structured_path`, but returns an iterator over variables instead of loading into
+/// environment.
+///
+/// The format is chosen from the extension of `path`: `json`, `yaml`, `yml` or `toml`, as far
+/// as the corresponding features are enabled.
+pub fn from_structured_path_iter<P: AsRef<Path>>(path: P) -> Result<StructuredIter> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or("");
+    let parse: fn(&str) -> Result<StructuredIter> = match extension {
+        #[cfg(feature = "json")]
+        "json" => StructuredIter::json,
+        #[cfg(feature = "yaml")]
+        "yaml" | "yml" => StructuredIter::yaml,
+        #[cfg(feature = "toml")]
+        "toml" => StructuredIter::toml,
+        _ => {
+            return Err(Error::InvalidDocument(
+                format!("unsupported extension '{extension}'").into(),
+            ))
+        }
+    };
+
+    let source = fs::read_to_string(path).map_err(Error::Io)?;
+    parse(&source)
+}
+
+fn flatten_into(pairs: &mut Vec<(String, String)>, key: String, node: Node) -> Result<()> {
+    let join = |segment: &str| {
+        if key.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{key}{SEPARATOR}{segment}")
+        }
+    };
+
+    match node {
+        Node::Map(entries) => {
+            for (segment, node) in entries {
+                flatten_into(pairs, join(&segment), node)?;
+            }
+        }
+        Node::List(items) => {
+            for (index, node) in items.into_iter().enumerate() {
+                flatten_into(pairs, join(&index.to_string()), node)?;
+            }
+        }
+        Node::Scalar(value) => {
+            // `env::set_var` panics on these
+            if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+                return Err(Error::InvalidDocument(
+                    format!("'{key}' cannot be used as a variable").into(),
+                ));
+            }
+            pairs.push((key, value));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+fn json_node(value: serde_json::Value) -> Node {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(map) => Node::Map(
+            map.into_iter()
+                .map(|(key, value)| (key, json_node(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Node::List(items.into_iter().map(json_node).collect()),
+        Value::String(value) => Node::Scalar(value),
+        Value::Null => Node::Scalar(String::new()),
+        value => Node::Scalar(value.to_string()),
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn yaml_node(value: serde_yaml::Value) -> Result<Node> {
+    use serde_yaml::Value;
+
+    Ok(match value {
+        Value::Mapping(map) => Node::Map(
+            map.into_iter()
+                .map(|(key, value)| match yaml_node(key)? {
+                    Node::Scalar(key) => Ok((key, yaml_node(value)?)),
+                    _ => Err(Error::InvalidDocument(Box::from(
+                        "keys must be strings, numbers or booleans",
+                    ))),
+                })
+                .collect::<Result<_>>()?,
+        ),
+        Value::Sequence(items) => {
+            Node::List(items.into_iter().map(yaml_node).collect::<Result<_>>()?)
+        }
+        Value::String(value) => Node::Scalar(value),
+        Value::Number(value) => Node::Scalar(value.to_string()),
+        Value::Bool(value) => Node::Scalar(value.to_string()),
+        Value::Null => Node::Scalar(String::new()),
+        Value::Tagged(tagged) => yaml_node(tagged.value)?,
+    })
+}
+
+#[cfg(feature = "toml")]
+fn toml_node(value: toml::Value) -> Node {
+    use toml::Value;
+
+    match value {
+        Value::Table(table) => Node::Map(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_node(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Node::List(items.into_iter().map(toml_node).collect()),
+        Value::String(value) => Node::Scalar(value),
+        Value::Integer(value) => Node::Scalar(value.to_string()),
+        Value::Float(value) => Node::Scalar(value.to_string()),
+        Value::Boolean(value) => Node::Scalar(value.to_string()),
+        Value::Datetime(value) => Node::Scalar(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(iter: Result<StructuredIter>) -> Vec<(String, String)> {
+        iter.unwrap().collect::<Result<_>>().unwrap()
+    }
+
+    fn expected(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_json() {
+        let source =
+            r#"{"db": {"hosts": ["a", {"port": 1}], "tls": true}, "name": null, "x": 1.5}"#;
+
+        assert_eq!(
+            pairs(StructuredIter::json(source)),
+            expected(&[
+                ("db__hosts__0", "a"),
+                ("db__hosts__1__port", "1"),
+                ("db__tls", "true"),
+                ("name", ""),
+                ("x", "1.5"),
+            ])
+        );
+        assert!(matches!(
+            StructuredIter::json("\"scalar\""),
+            Err(Error::InvalidDocument(_))
+        ));
+        assert!(matches!(
+            StructuredIter::json(r#"{"a=b": 1}"#),
+            Err(Error::InvalidDocument(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "yaml")]
+    fn test_yaml() {
+        let source = "db:\n  hosts: [a, b]\n  port: 5432\n1: one\nenabled: !custom yes\n";
+
+        assert_eq!(
+            pairs(StructuredIter::yaml(source)),
+            expected(&[
+                ("db__hosts__0", "a"),
+                ("db__hosts__1", "b"),
+                ("db__port", "5432"),
+                ("1", "one"),
+                ("enabled", "yes"),
+            ])
+        );
+        assert!(StructuredIter::yaml("[a, b]: c").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn test_toml() {
+        let source = "name = \"app\"\n[db]\nports = [1, 2]\nready = false\n";
+
+        assert_eq!(
+            pairs(StructuredIter::toml(source)),
+            expected(&[
+                ("db__ports__0", "1"),
+                ("db__ports__1", "2"),
+                ("db__ready", "false"),
+                ("name", "app"),
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn test_load_shares_override_semantics() {
+        use std::env;
+
+        use crate::scoped::EnvGuard;
+
+        let _lock = crate::lock_env();
+        let _existing = EnvGuard::capture(["STRUCTURED_KEPT"]);
+        env::set_var("STRUCTURED_KEPT", "before");
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.local.json");
+        fs::write(
+            &path,
+            r#"{"STRUCTURED_KEPT": "file", "STRUCTURED_NEW": "file"}"#,
+        )
+        .unwrap();
+
+        let guard = from_structured_path_iter(&path)
+            .unwrap()
+            .load_scoped()
+            .unwrap();
+        assert_eq!(env::var("STRUCTURED_KEPT").unwrap(), "before");
+        assert_eq!(env::var("STRUCTURED_NEW").unwrap(), "file");
+        drop(guard);
+
+        let _guard = EnvGuard::capture(["STRUCTURED_NEW"]);
+        from_structured_path_iter(&path)
+            .unwrap()
+            .load_override()
+            .unwrap();
+        assert_eq!(env::var("STRUCTURED_KEPT").unwrap(), "file");
+    }
+
+    #[test]
+    fn test_unsupported_extension() {
+        let err = from_structured_path_iter("config.ini").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid document: unsupported extension 'ini'"
+        );
+    }
+}
@@ -9,21 +9,42 @@ use std::{
 
 use tempfile::TempDir;
 
+use crate::iter::Iter;
 use crate::{
-    dotenv, dotenv_iter, from_filename, from_filename_iter, from_path, from_path_iter, vars,
+    dotenv, dotenv_iter, from_filename, from_filename_iter, from_path, from_path_iter, lock_env,
+    vars, Dialect, DuplicatePolicy, EnvFile, EnvGuard, EnvLock, Error, ParseErrorKind,
 };
 
-fn init(value: &str) -> TempDir {
+/// Keeps a test's working directory and environment to itself until dropped.
+///
+/// Fields are dropped in declaration order, so the environment is restored before the lock
+/// is released.
+struct TestEnv {
+    _dir: TempDir,
+    _env: EnvGuard,
+    _lock: EnvLock,
+}
+
+fn init(value: &str) -> TestEnv {
+    let lock = lock_env();
+    let keys = Iter::new(value.as_bytes()).filter_map(|item| item.ok().map(|(key, _)| key));
+    let guard = EnvGuard::capture(keys);
+
     let dir = TempDir::new().unwrap();
     env::set_current_dir(&dir).unwrap();
     let dotenv_path = dir.path().join(".env");
     let mut dotenv_file = File::create(dotenv_path).unwrap();
     dotenv_file.write_all(value.as_bytes()).unwrap();
     dotenv_file.flush().unwrap();
-    dir
+
+    TestEnv {
+        _dir: dir,
+        _env: guard,
+        _lock: lock,
+    }
 }
 
-fn init_default() -> TempDir {
+fn init_default() -> TestEnv {
     init("TESTKEY=test_val")
 }
 
@@ -106,6 +127,27 @@ fn test_from_path() {
     assert_eq!(var("TESTKEY").unwrap(), "test_val");
 }
 
+#[test]
+fn test_from_str() {
+    let _lock = lock_env();
+    let _guard = EnvGuard::capture(["FROM_STR_KEY", "FROM_STR_SUBSTITUTED"]);
+
+    crate::from_str("FROM_STR_KEY=value\nFROM_STR_SUBSTITUTED=${FROM_STR_KEY}2").unwrap();
+
+    assert_eq!(var("FROM_STR_KEY").unwrap(), "value");
+    assert_eq!(var("FROM_STR_SUBSTITUTED").unwrap(), "value2");
+}
+
+#[test]
+fn test_from_read_iter_ignores_includes() {
+    let pairs: Vec<(String, String)> =
+        crate::from_read_iter(b"# @include missing.env\nFROM_READER=1".as_ref())
+            .collect::<crate::Result<_>>()
+            .unwrap();
+
+    assert_eq!(pairs, [("FROM_READER".to_string(), "1".to_string())]);
+}
+
 #[test]
 fn test_var() {
     let _guard = init_default();
@@ -117,9 +159,6 @@ fn test_var() {
 
 #[test]
 fn test_variable_substitutions() {
-    std::env::set_var("KEY", "value");
-    std::env::set_var("KEY1", "value1");
-
     let substitutions_to_test = [
         "$ZZZ", "$KEY", "$KEY1", "${KEY}1", "$KEY_U", "${KEY_U}", "\\$KEY",
     ];
@@ -136,6 +175,9 @@ fn test_variable_substitutions() {
     SUBSTITUTION_WITHOUT_QUOTES={common_string}
     "#,
     ));
+    let _vars = EnvGuard::capture(["KEY", "KEY1"]);
+    env::set_var("KEY", "value");
+    env::set_var("KEY1", "value1");
 
     dotenv().ok();
 
@@ -182,3 +224,276 @@ fn test_vars() {
 
     assert_eq!(vars["TESTKEY"], "test_val");
 }
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_dotenv_async() {
+    let _guard = init_default();
+
+    crate::dotenv_async().await.ok();
+    assert_eq!(var("TESTKEY").unwrap(), "test_val");
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_find_all_async() {
+    let _guard = init_default();
+    fs::write(".env.local", "").unwrap();
+    let defaults = TempDir::new().unwrap();
+    fs::write(defaults.path().join(".env"), "").unwrap();
+
+    let found = crate::Finder::new()
+        .filenames([".env.local", ".env"])
+        .directory(defaults.path())
+        .find_all_async()
+        .await
+        .unwrap();
+    let names: Vec<_> = found.iter().map(|path| path.file_name().unwrap()).collect();
+    assert_eq!(names, [".env.local", ".env", ".env"]);
+    assert!(found[2].starts_with(defaults.path()));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_from_path_iter_async() {
+    let _guard = init_default();
+
+    let mut path = env::current_dir().unwrap();
+    path.push(".env");
+
+    let pairs: Vec<(String, String)> = crate::from_path_iter_async(&path)
+        .await
+        .unwrap()
+        .collect::<crate::Result<_>>()
+        .unwrap();
+
+    assert_eq!(pairs, [("TESTKEY".to_string(), "test_val".to_string())]);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_include_is_rejected() {
+    let _guard = init("# @include common.env\nASYNC_KEY=1\n");
+    fs::write("common.env", "ASYNC_COMMON=1\n").unwrap();
+
+    let result: crate::Result<Vec<_>> =
+        crate::from_path_iter_async(".env").await.unwrap().collect();
+    assert!(matches!(result, Err(Error::AsyncInclude(path)) if path.ends_with("common.env")));
+
+    let result = crate::dotenv_async().await;
+    assert!(matches!(result, Err(Error::AsyncInclude(_))));
+    assert!(var("ASYNC_COMMON").is_err());
+}
+
+#[test]
+fn test_include_directive() {
+    let _guard = init("INCLUDED_SHARED=shared\nINCLUDED_OVERRIDE=common\n");
+    fs::create_dir("child").unwrap();
+    fs::write(
+        "child/app.env",
+        "# @include ../.env\nINCLUDED_OVERRIDE=local\nFROM_COMMON=${INCLUDED_SHARED}\n\
+         FROM_OVERRIDE=${INCLUDED_OVERRIDE}\n",
+    )
+    .unwrap();
+
+    let pairs: Vec<(String, String)> = from_path_iter("child/app.env")
+        .unwrap()
+        .collect::<crate::Result<_>>()
+        .unwrap();
+
+    assert_eq!(
+        pairs,
+        [
+            ("INCLUDED_SHARED".to_string(), "shared".to_string()),
+            ("INCLUDED_OVERRIDE".to_string(), "common".to_string()),
+            ("INCLUDED_OVERRIDE".to_string(), "local".to_string()),
+            ("FROM_COMMON".to_string(), "shared".to_string()),
+            ("FROM_OVERRIDE".to_string(), "local".to_string()),
+        ]
+    );
+
+    from_path("child/app.env").unwrap();
+    assert_eq!(var("INCLUDED_OVERRIDE").unwrap(), "local");
+}
+
+#[test]
+fn test_include_does_not_override_own_entries() {
+    let _guard = init("INCLUDED_OWN=common\nINCLUDED_OTHER=common\n");
+    fs::write(
+        "app.env",
+        "INCLUDED_OWN=local\n# @include .env\nINCLUDED_OTHER=local\n",
+    )
+    .unwrap();
+
+    for policy in [
+        DuplicatePolicy::FirstWins,
+        DuplicatePolicy::LastWins,
+        DuplicatePolicy::Error,
+    ] {
+        let env = EnvFile::read(from_path_iter("app.env").unwrap().duplicates(policy)).unwrap();
+        assert_eq!(env.get("INCLUDED_OWN"), Some("local"), "{policy:?}");
+        assert_eq!(env.get("INCLUDED_OTHER"), Some("local"), "{policy:?}");
+    }
+}
+
+#[test]
+fn test_duplicate_in_sibling_includes() {
+    let _guard = init("# @include a.env\n# @include b.env\n");
+    fs::write("a.env", "INCLUDED_TWICE=a\n").unwrap();
+    fs::write("b.env", "# comment\nINCLUDED_TWICE=b\n").unwrap();
+
+    let result: crate::Result<Vec<_>> = dotenv_iter()
+        .unwrap()
+        .duplicates(DuplicatePolicy::Error)
+        .collect();
+
+    match result {
+        Err(Error::DuplicateIncludedKey(key, first_path, 1, second_path, 2)) => {
+            assert_eq!(key, "INCLUDED_TWICE");
+            assert!(first_path.ends_with("a.env"));
+            assert!(second_path.ends_with("b.env"));
+        }
+        other => panic!("Expected a duplicate key in two includes, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_multiline_value_ends_with_its_file() {
+    let _guard = init("# @include open.env\nINCLUDE_AFTER='closed'\n");
+    fs::write("open.env", "INCLUDE_OPEN='never closed\n").unwrap();
+
+    let result: crate::Result<Vec<_>> = dotenv_iter().unwrap().dialect(Dialect::node()).collect();
+
+    match result {
+        Err(Error::LineParse(err)) => {
+            assert_eq!(err.kind(), ParseErrorKind::UnterminatedQuote);
+            assert_eq!(err.line(), "INCLUDE_OPEN='never closed");
+        }
+        other => panic!("Expected an unterminated quote, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_include_cycle() {
+    let _guard = init("# @include other.env\n");
+    fs::write("other.env", "# @include .env\n").unwrap();
+
+    let result: crate::Result<Vec<_>> = dotenv_iter().unwrap().collect();
+
+    match result {
+        Err(Error::IncludeCycle(path)) => assert!(path.ends_with(".env")),
+        other => panic!("Expected an include cycle, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_include_max_depth() {
+    let _guard = init("# @include 1.env\n");
+    fs::write("1.env", "# @include 2.env\n").unwrap();
+    fs::write("2.env", "INCLUDE_DEPTH=2\n").unwrap();
+
+    let result: crate::Result<Vec<_>> = dotenv_iter().unwrap().max_include_depth(1).collect();
+    assert!(matches!(result, Err(Error::IncludeTooDeep(path)) if path.ends_with("2.env")));
+
+    let result: crate::Result<Vec<_>> = dotenv_iter().unwrap().max_include_depth(2).collect();
+    assert_eq!(result.unwrap().len(), 1);
+}
+
+/// Checks `Dialect::shell_compat` against `/bin/sh`, which only exists on Unix.
+#[cfg(unix)]
+mod shell_compat {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Files that `Dialect::shell_compat` must read exactly like `/bin/sh` does.
+    const SHELL_CASES: &[&str] = &[
+        "SH_PLAIN=value",
+        "  export SH_EXPORTED=1\n\n# comment\nSH_AFTER=2 # trailing comment",
+        "SH_HASH=a#b\nSH_LEADING_HASH=#c\nSH_URL=http://host/path?q=1#frag",
+        r"SH_ESCAPED=a\ b\#c\q\\d\$e",
+        r#"SH_SINGLE='$SH_NOPE \n "x"'"#,
+        r#"SH_DOUBLE="\$ \" \\ \n \q 'x' ${SH_UNDEFINED_IN_SH}""#,
+        "SH_FIRST=one\nSH_BRACED=${SH_FIRST}two\nSH_BARE=$SH_FIRST-three\nSH_QUOTED=\"$SH_FIRST four\"",
+        "SH_MIXED=a'b c'\"d $ e\"f$\nSH_DOLLAR=$ # comment",
+        "SH_EMPTY=\nSH_EMPTY_QUOTES=''\nSH_EMPTY_COMMENT= # comment",
+        "SH_DUPLICATE=first\nSH_DUPLICATE=second\nSH_USES_DUPLICATE=$SH_DUPLICATE",
+        "SH_GLOB=*.rs\nSH_BRACES={a,b}\nSH_BRACKETS=[ab]\nSH_EQUALS=a=b",
+        "SH_UNICODE=héllo\nSH_TRAILING_SPACE=a\\ ",
+        "export=value\nSH_TAB=a\t# comment",
+    ];
+
+    /// Files that `Dialect::shell_compat` rejects because it cannot replicate what the shell does.
+    const UNSUPPORTED_SHELL_CASES: &[&str] = &[
+        "SH_COMMAND=$(echo hi)",
+        "SH_BACKTICK=`echo hi`",
+        "SH_DEFAULT=${SH_NOPE:-default}",
+        "SH_TWO_WORDS=a b",
+        "SH_SPACED = a",
+        "SH_TILDE=~/bin",
+        "SH_MULTILINE=\"a\nb\"",
+        "SH_CONTINUED=a\\\nb",
+        "SH_SEPARATED=a; SH_OTHER=b",
+    ];
+
+    /// Sources `contents` with `/bin/sh` in an empty environment, returning the variables it exports.
+    fn source_with_sh(dir: &TempDir, contents: &str) -> HashMap<String, String> {
+        let path = dir.path().join("case.env");
+        fs::write(&path, contents).unwrap();
+
+        let output = std::process::Command::new("/usr/bin/env")
+            .args([
+                "-i",
+                "/bin/sh",
+                "-c",
+                "set -a; . \"$1\"; exec /usr/bin/env -0",
+                "sh",
+            ])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "sh failed on {contents:?}");
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_shell_compat_matches_sh() {
+        assert!(
+            Path::new("/bin/sh").exists(),
+            "comparing with the shell needs /bin/sh"
+        );
+        let _lock = lock_env();
+        let dir = TempDir::new().unwrap();
+        let baseline = source_with_sh(&dir, "");
+
+        for contents in SHELL_CASES {
+            let mut expected = source_with_sh(&dir, contents);
+            expected.retain(|key, _| !baseline.contains_key(key));
+
+            let actual = Iter::new(contents.as_bytes())
+                .dialect(Dialect::shell_compat())
+                .duplicates(DuplicatePolicy::LastWins)
+                .collect::<crate::Result<HashMap<_, _>>>()
+                .unwrap();
+
+            assert_eq!(actual, expected, "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn test_shell_compat_rejects_unsupported() {
+        for contents in UNSUPPORTED_SHELL_CASES {
+            let result = Iter::new(contents.as_bytes())
+                .dialect(Dialect::shell_compat())
+                .collect::<crate::Result<Vec<_>>>();
+
+            assert!(result.is_err(), "{contents:?} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::diff::Diff;
+use crate::errors::{Error, Result};
+use crate::iter::Iter;
+
+/// Watches a .env file and reports what changed every time it is written.
+///
+/// The file is watched for as long as the `Watcher` is alive.
+pub struct Watcher {
+    _inner: RecommendedWatcher,
+}
+
+impl Watcher {
+    /// Starts watching the file at `path`, calling `on_change` with the diff against the
+    /// previous contents whenever it changes.
+    ///
+    /// The callback runs on a background thread. It receives an error instead of a diff if
+    /// the file can no longer be read or parsed, in which case the previous contents are kept
+    /// as the baseline for the next change.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use sd_dotenv::Watcher;
+    ///
+    /// let _watcher = Watcher::new(".env", |diff| {
+    ///     if let Ok(diff) = diff {
+    ///         diff.apply();
+    ///     }
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn new<P, F>(path: P, mut on_change: F) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnMut(Result<Diff>) + Send + 'static,
+    {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| Error::Io(io::ErrorKind::InvalidInput.into()))?
+            .to_owned();
+        // Editors usually save by replacing the file, which drops a watch on the file itself,
+        // so the parent directory is watched instead.
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let path = directory.join(&file_name);
+
+        let mut current = read(&path)?;
+
+        let mut inner = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => return on_change(Err(Error::Watch(err))),
+            };
+            if matches!(event.kind, EventKind::Access(_))
+                || !event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == Some(&file_name))
+            {
+                return;
+            }
+
+            match read(&path) {
+                Ok(next) => {
+                    let diff = Diff::between(&current, &next);
+                    current = next;
+                    if !diff.is_empty() {
+                        on_change(Ok(diff));
+                    }
+                }
+                Err(err) => on_change(Err(err)),
+            }
+        })
+        .map_err(Error::Watch)?;
+        inner
+            .watch(&directory, RecursiveMode::NonRecursive)
+            .map_err(Error::Watch)?;
+
+        Ok(Self { _inner: inner })
+    }
+
+    /// Like `new`, but sends every diff to the returned channel instead of calling a callback.
+    pub fn channel<P: AsRef<Path>>(path: P) -> Result<(Self, Receiver<Result<Diff>>)> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = Self::new(path, move |diff| {
+            sender.send(diff).ok();
+        })?;
+        Ok((watcher, receiver))
+    }
+}
+
+/// Parses the file at `path`, treating a missing file as an empty one.
+fn read(path: &Path) -> Result<HashMap<String, String>> {
+    match File::open(path) {
+        Ok(file) => Iter::new(file).with_path(path).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(Error::Io(err)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_watcher_reports_changes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "KEPT=1\nCHANGED=old\nREMOVED=x\n").unwrap();
+
+        let (_watcher, diffs) = Watcher::channel(&path).unwrap();
+        // replace the file in one step, like editors do, so no half-written state is observed
+        let staged = dir.path().join(".env.tmp");
+        fs::write(&staged, "KEPT=1\nCHANGED=new\nADDED=y\n").unwrap();
+        fs::rename(&staged, &path).unwrap();
+
+        let diff = diffs.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(
+            diff,
+            Diff::between(
+                &map(&[("KEPT", "1"), ("CHANGED", "old"), ("REMOVED", "x")]),
+                &map(&[("KEPT", "1"), ("CHANGED", "new"), ("ADDED", "y")]),
+            )
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Drives the `dotenv-lsp` binary over stdin and stdout, the way an editor does.
+
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use lsp_server::{Message, Notification, Request, RequestId};
+use lsp_types::notification::{
+    DidOpenTextDocument, Exit, Initialized, Notification as NotificationType,
+};
+use lsp_types::request::{HoverRequest, Initialize, Request as RequestType, Shutdown};
+use lsp_types::{
+    DidOpenTextDocumentParams, Hover, HoverContents, HoverParams, InitializeParams,
+    InitializedParams, Position, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Uri, WorkDoneProgressParams,
+};
+
+struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: i32,
+}
+
+impl Server {
+    fn spawn(command: &mut Command) -> Self {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut server = Self {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        };
+        server.request::<Initialize>(InitializeParams::default());
+        server.notify::<Initialized>(InitializedParams {});
+        server
+    }
+
+    fn request<R: RequestType>(&mut self, params: R::Params) -> serde_json::Value {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), R::METHOD.to_owned(), params);
+        Message::from(request).write(&mut self.stdin).unwrap();
+        self.stdin.flush().unwrap();
+
+        loop {
+            match Message::read(&mut self.stdout).unwrap() {
+                Some(Message::Response(response)) if response.id == id => {
+                    assert!(response.error.is_none(), "{:?}", response.error);
+                    // a `null` result is read back as no result
+                    return response.result.unwrap_or_default();
+                }
+                Some(_) => {}
+                None => panic!("the server exited before responding to {}", R::METHOD),
+            }
+        }
+    }
+
+    fn notify<N: NotificationType>(&mut self, params: N::Params) {
+        let notification = Notification::new(N::METHOD.to_owned(), params);
+        Message::from(notification).write(&mut self.stdin).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn hover(&mut self, line: u32, character: u32) -> String {
+        let hover = self.request::<HoverRequest>(HoverParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri()),
+                Position::new(line, character),
+            ),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        });
+        match serde_json::from_value::<Hover>(hover).unwrap().contents {
+            HoverContents::Markup(contents) => contents.value,
+            other => panic!("Expected markup, got {other:?}"),
+        }
+    }
+
+    fn shutdown(mut self) {
+        self.request::<Shutdown>(());
+        self.notify::<Exit>(());
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn uri() -> Uri {
+    "file:///project/.env".parse().unwrap()
+}
+
+#[test]
+fn test_hover_over_stdio() {
+    let mut server = Server::spawn(
+        Command::new(env!("CARGO_BIN_EXE_dotenv-lsp"))
+            .env("LSP_STDIO_HOST", "from_env")
+            .env_remove("LSP_STDIO_PORT"),
+    );
+    server.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(
+            uri(),
+            String::from("dotenv"),
+            1,
+            String::from(
+                "LSP_STDIO_HOST=from_file\nLSP_STDIO_PORT=80\n\
+                 LSP_STDIO_URL=${LSP_STDIO_HOST}:${LSP_STDIO_PORT}\n",
+            ),
+        ),
+    });
+
+    // the environment takes precedence, as it does when the file is parsed
+    assert_eq!(
+        server.hover(2, 18),
+        "```\nLSP_STDIO_HOST=from_env\n```\nfrom the environment"
+    );
+    assert_eq!(server.hover(2, 35), "```\nLSP_STDIO_PORT=80\n```");
+    server.shutdown();
+}
+
+#[test]
+fn test_hover_ignores_later_definitions() {
+    let mut server =
+        Server::spawn(Command::new(env!("CARGO_BIN_EXE_dotenv-lsp")).env_remove("LSP_STDIO_LATER"));
+    server.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(
+            uri(),
+            String::from("dotenv"),
+            1,
+            String::from("LSP_STDIO_EARLY=${LSP_STDIO_LATER}\nLSP_STDIO_LATER=1\n"),
+        ),
+    });
+
+    // `LSP_STDIO_EARLY` parses to an empty value, as the key is only defined after it
+    assert_eq!(server.hover(0, 18), "`LSP_STDIO_LATER` is not set");
+    server.shutdown();
+}